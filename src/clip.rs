@@ -1,11 +1,15 @@
 use arboard::{Clipboard, ImageData};
+use base64::{Engine as _, engine::general_purpose};
 use blake3::Hash;
 use chrono::Utc;
 use crossbeam::channel::Sender;
+use std::collections::HashMap;
+use std::sync::{Arc, Once, RwLock};
 use std::{thread, time::Duration};
 
-use crate::img::{base64_to_imagedata, image_to_base64};
-use crate::types::{ClipboardContent, ClipboardEntry};
+use crate::img::{base64_to_imagedata, image_to_base64, png_to_base64, thumbnail};
+use crate::prefs::Capture;
+use crate::types::{ClipboardContent, ClipboardEntry, Selection, Target, Usage};
 
 pub fn clipboard_entry_hash(c: &ClipboardContent) -> Hash {
     match c {
@@ -18,51 +22,475 @@ pub fn content_key(c: &ClipboardContent) -> String {
     clipboard_entry_hash(c).to_hex().to_string()
 }
 
-pub fn read_clipboard() -> Result<Option<ClipboardContent>, arboard::Error> {
+#[cfg(target_os = "linux")]
+fn linux_kind(selection: Selection) -> arboard::LinuxClipboardKind {
+    match selection {
+        Selection::Clipboard => arboard::LinuxClipboardKind::Clipboard,
+        Selection::Primary => arboard::LinuxClipboardKind::Primary,
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn get(clipboard: &mut Clipboard, selection: Selection) -> arboard::Get<'_> {
+    use arboard::GetExtLinux;
+    clipboard.get().clipboard(linux_kind(selection))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn get(clipboard: &mut Clipboard, _selection: Selection) -> arboard::Get<'_> {
+    clipboard.get()
+}
+
+#[cfg(target_os = "linux")]
+fn set(clipboard: &mut Clipboard, selection: Selection) -> arboard::Set<'_> {
+    use arboard::SetExtLinux;
+    clipboard.set().clipboard(linux_kind(selection))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set(clipboard: &mut Clipboard, _selection: Selection) -> arboard::Set<'_> {
+    clipboard.set()
+}
+
+pub fn read_clipboard(selection: Selection) -> Result<Option<ClipboardContent>, arboard::Error> {
+    // Only X11 and Wayland have a PRIMARY selection
+    if cfg!(not(target_os = "linux")) && selection == Selection::Primary {
+        return Ok(None);
+    }
     let mut clipboard: Clipboard = Clipboard::new()?;
 
-    if let Ok(txt) = clipboard.get_text() {
+    if let Ok(txt) = get(&mut clipboard, selection).text() {
         return Ok(Some(ClipboardContent::Text(txt)));
     }
-    if let Ok(img) = clipboard.get_image() {
+    if let Ok(img) = get(&mut clipboard, selection).image() {
         return Ok(Some(ClipboardContent::ImageBase64(image_to_base64(&img))));
     }
     Ok(None)
 }
 
-pub fn set_clipboard(content: &ClipboardContent) -> Result<(), arboard::Error> {
+/// The focused window of another application, for `paste_into` to go back
+/// to. Only X11 lets ClipVault find and type into it.
+pub fn focused_window() -> Option<u32> {
+    #[cfg(target_os = "linux")]
+    if !crate::wayland::available() {
+        return crate::x11::active_window().unwrap_or_else(|e| {
+            eprintln!("Failed to find the focused window: {e}");
+            None
+        });
+    }
+    None
+}
+
+/// Focuses `window` once ClipVault's own window is gone and pastes into it,
+/// from a background thread.
+pub fn paste_into(window: u32, terminals: Vec<String>) {
+    #[cfg(target_os = "linux")]
+    thread::spawn(move || {
+        thread::sleep(PASTE_DELAY);
+        if let Err(e) = crate::x11::paste_into(window, &terminals) {
+            eprintln!("Paste failed: {e}");
+        }
+    });
+    #[cfg(not(target_os = "linux"))]
+    let _ = (window, terminals);
+}
+
+/// Reads the text or image currently on `selection`. Unlike `read_clipboard`
+/// this also sees native Wayland clients, which XWayland only mirrors while
+/// one of its windows has focus.
+pub fn read_selection(selection: Selection) -> anyhow::Result<Option<ClipboardContent>> {
+    #[cfg(target_os = "linux")]
+    if crate::wayland::available() {
+        let mut source = crate::wayland::WaylandSource::connect()?;
+        let capture = Capture {
+            rich_targets: false,
+            respect_secret_hints: false,
+            ..Capture::default()
+        };
+        return Ok(source.read(selection, &capture)?.map(|d| d.content));
+    }
+    Ok(read_clipboard(selection)?)
+}
+
+/// Puts `content` on `selection`, offering the extra `targets` next to it where
+/// the platform allows. Pass no targets to paste plain text only.
+pub fn set_clipboard(
+    content: &ClipboardContent,
+    targets: &[Target],
+    selection: Selection,
+) -> anyhow::Result<()> {
+    if cfg!(not(target_os = "linux")) && selection == Selection::Primary {
+        return Ok(());
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        if crate::wayland::available() {
+            match crate::wayland::set_selection(offered_targets(content, targets)?, selection) {
+                Ok(()) => return Ok(()),
+                Err(e) => eprintln!("Wayland data-control unavailable, using X11: {e}"),
+            }
+        }
+        match crate::x11::set_selection(offered_targets(content, targets)?, selection) {
+            Ok(()) => return Ok(()),
+            Err(e) => eprintln!("X11 selection unavailable, using arboard: {e}"),
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = targets;
+
     let mut clipboard: Clipboard = Clipboard::new()?;
     match content {
-        ClipboardContent::Text(t) => clipboard.set_text(t.clone()),
+        ClipboardContent::Text(t) => set(&mut clipboard, selection).text(t.clone())?,
         ClipboardContent::ImageBase64(b64) => {
-            let img: ImageData<'_> =
-                base64_to_imagedata(b64).map_err(|_| arboard::Error::ContentNotAvailable)?;
-            clipboard.set_image(img)
+            let img: ImageData<'_> = base64_to_imagedata(b64)?;
+            set(&mut clipboard, selection).image(img)?
         }
     }
+    Ok(())
 }
 
-pub fn spawn_watcher(tx: Sender<ClipboardEntry>, mut last_hash: Option<Hash>) {
+/// Every MIME type and its data that gets offered for `content`.
+#[cfg(target_os = "linux")]
+fn offered_targets(
+    content: &ClipboardContent,
+    targets: &[Target],
+) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+    let mut offered: Vec<(String, Vec<u8>)> = match content {
+        ClipboardContent::Text(t) => TEXT_MIMES
+            .iter()
+            .map(|m| (m.to_string(), t.as_bytes().to_vec()))
+            .collect(),
+        ClipboardContent::ImageBase64(b64) => {
+            vec![(
+                IMAGE_MIME.to_string(),
+                general_purpose::STANDARD.decode(b64)?,
+            )]
+        }
+    };
+    for t in targets {
+        if !offered.iter().any(|(mime, _)| *mime == t.mime) {
+            offered.push((t.mime.clone(), general_purpose::STANDARD.decode(&t.data)?));
+        }
+    }
+    Ok(offered)
+}
+
+// Text targets in order of preference when reading, all of them are offered when writing
+pub const TEXT_MIMES: &[&str] = &[
+    "text/plain;charset=utf-8",
+    "UTF8_STRING",
+    "text/plain",
+    "STRING",
+    "TEXT",
+];
+pub const IMAGE_MIME: &str = "image/png";
+
+/// Whether `mime` is worth keeping next to `content`. Plain text variants and
+/// image conversions only restate the content, and X11 also lists atoms like
+/// `TARGETS` or `SAVE_TARGETS` that aren't data at all.
+fn is_extra_target(mime: &str, content: &ClipboardContent) -> bool {
+    if mime.starts_with("text/plain") || mime == "application/x-qt-image" {
+        return false;
+    }
+    if mime.starts_with("image/") {
+        return mime == "image/svg+xml"
+            || (mime == IMAGE_MIME && matches!(content, ClipboardContent::Text(_)));
+    }
+    mime.contains('/')
+}
+
+/// What a selection holds: the text or image shown in the history, plus the
+/// other targets it was offered as.
+pub struct SelectionData {
+    pub content: ClipboardContent,
+    pub targets: Vec<Target>,
+}
+
+// KeePassXC, KDE and arboard mark secrets with this target, holding "secret"
+const KDE_PASSWORD_HINT: &str = "x-kde-passwordManagerHint";
+// The macOS pasteboard conventions, also offered by some cross-platform apps
+const CONCEALED_HINTS: &[&str] = &[
+    "org.nspasteboard.ConcealedType",
+    "org.nspasteboard.TransientType",
+];
+
+/// Whether the owner asked clipboard managers not to record this copy. A hint
+/// that can't be read counts as set.
+fn marked_secret(
+    mimes: &[String],
+    fetch: &mut impl FnMut(&str) -> anyhow::Result<Vec<u8>>,
+) -> bool {
+    mimes.iter().any(|m| {
+        if m == KDE_PASSWORD_HINT {
+            fetch(m).map_or(true, |v| v.trim_ascii() == b"secret")
+        } else {
+            CONCEALED_HINTS.contains(&m.as_str())
+        }
+    })
+}
+
+/// Whether one of `owner`'s names is on the `ignored` list. Names match
+/// case-insensitively, either whole or by their last reverse-DNS component,
+/// so `keepassxc` also covers the `org.keepassxc.KeePassXC` app id.
+fn is_ignored(owner: &[String], ignored: &[String]) -> bool {
+    owner.iter().any(|name| {
+        let name = name.to_lowercase();
+        ignored.iter().any(|app| {
+            let app = app.trim().to_lowercase();
+            !app.is_empty() && (name == app || name.rsplit('.').next() == Some(app.as_str()))
+        })
+    })
+}
+
+/// Builds the `SelectionData` for a selection offering `mimes`, using `fetch`
+/// to transfer a single target. Copies marked as secret are never transferred.
+pub fn read_offered(
+    mimes: &[String],
+    capture: &Capture,
+    mut fetch: impl FnMut(&str) -> anyhow::Result<Vec<u8>>,
+) -> anyhow::Result<Option<SelectionData>> {
+    if capture.respect_secret_hints && marked_secret(mimes, &mut fetch) {
+        return Ok(None);
+    }
+    let offered = |m: &str| mimes.iter().any(|o| o == m);
+
+    let content = if let Some(mime) = TEXT_MIMES.iter().find(|m| offered(m)) {
+        let data = fetch(mime)?;
+        if data.is_empty() {
+            return Ok(None);
+        }
+        ClipboardContent::Text(String::from_utf8_lossy(&data).into_owned())
+    } else if offered(IMAGE_MIME) {
+        ClipboardContent::ImageBase64(png_to_base64(&fetch(IMAGE_MIME)?)?)
+    } else {
+        return Ok(None);
+    };
+
+    let mut targets = Vec::new();
+    if capture.rich_targets {
+        let limit = |kb: u32| {
+            if kb == 0 {
+                usize::MAX
+            } else {
+                kb as usize * 1024
+            }
+        };
+        let max_target = limit(capture.max_target_kb);
+        let mut budget = limit(capture.max_targets_kb);
+
+        for mime in mimes.iter().filter(|m| is_extra_target(m, &content)) {
+            let Ok(data) = fetch(mime) else {
+                continue;
+            };
+            if data.is_empty() || data.len() > max_target || data.len() > budget {
+                continue;
+            }
+            budget -= data.len();
+            targets.push(Target {
+                mime: mime.clone(),
+                data: general_purpose::STANDARD.encode(&data),
+            });
+        }
+    }
+
+    Ok(Some(SelectionData { content, targets }))
+}
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+// Dragging out a mouse selection changes PRIMARY many times a second
+const PRIMARY_SETTLE: Duration = Duration::from_millis(400);
+// Gives the window manager time to unmap ClipVault's window
+#[cfg(target_os = "linux")]
+const PASTE_DELAY: Duration = Duration::from_millis(100);
+
+/// Blocks until a selection may have changed. Spurious wake-ups are fine,
+/// the watcher deduplicates by content hash.
+pub trait ChangeSource: Send {
+    /// Returns the selection that changed, or `None` if the source can't tell.
+    fn wait(&mut self) -> anyhow::Result<Option<Selection>>;
+
+    /// Reads the current content of `selection`. Sources with their own
+    /// connection to the clipboard override this and also collect the extra
+    /// targets, the rest go through arboard, which only knows text and images.
+    ///
+    /// Going through arboard, the targets password managers mark secret
+    /// copies with aren't visible either, so those copies are recorded like
+    /// any other and only the secret detectors can catch them.
+    fn read(
+        &mut self,
+        selection: Selection,
+        capture: &Capture,
+    ) -> anyhow::Result<Option<SelectionData>> {
+        if capture.respect_secret_hints {
+            static WARNED: Once = Once::new();
+            WARNED.call_once(|| {
+                eprintln!(
+                    "Password manager hints can't be read on this platform, \
+                     copies they mark as secret are recorded"
+                );
+            });
+        }
+        Ok(read_clipboard(selection)?.map(|content| SelectionData {
+            content,
+            targets: Vec::new(),
+        }))
+    }
+
+    /// Names of the application owning `selection` (WM_CLASS, process name or
+    /// app id), empty if the source can't tell.
+    fn owner(&mut self, _selection: Selection) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// Fallback for sessions without selection-change notifications.
+pub struct PollSource {
+    interval: Duration,
+}
+
+impl ChangeSource for PollSource {
+    fn wait(&mut self) -> anyhow::Result<Option<Selection>> {
+        thread::sleep(self.interval);
+        Ok(None)
+    }
+}
+
+fn change_source() -> Box<dyn ChangeSource> {
+    // Through XWayland, X11 only sees changes while one of its windows has focus
+    #[cfg(target_os = "linux")]
+    if crate::wayland::available() {
+        match crate::wayland::WaylandSource::connect() {
+            Ok(source) => return Box::new(source),
+            Err(e) => eprintln!("Wayland data-control unavailable, falling back to X11: {e}"),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    match crate::x11::XFixesSource::connect() {
+        Ok(source) => return Box::new(source),
+        Err(e) => eprintln!("XFixes unavailable, polling the clipboard instead: {e}"),
+    }
+
+    Box::new(PollSource {
+        interval: POLL_INTERVAL,
+    })
+}
+
+/// Reads `selection`, waiting for PRIMARY to stop changing first so only the
+/// finished mouse selection is recorded. Returns `None` if nothing new is there.
+fn read_new(
+    source: &mut dyn ChangeSource,
+    selection: Selection,
+    capture: &Capture,
+    last: Option<Hash>,
+) -> anyhow::Result<Option<(SelectionData, Hash)>> {
+    if is_ignored(&source.owner(selection), &capture.ignored_apps) {
+        return Ok(None);
+    }
+    let Some(mut data) = source.read(selection, capture)? else {
+        return Ok(None);
+    };
+    // What an auto-clear leaves behind, see `ClipApp::clear_now`
+    if matches!(&data.content, ClipboardContent::Text(t) if t.is_empty()) {
+        return Ok(None);
+    }
+    let mut h: Hash = clipboard_entry_hash(&data.content);
+
+    if selection == Selection::Primary {
+        while Some(h) != last {
+            thread::sleep(PRIMARY_SETTLE);
+            let Some(next) = source.read(selection, capture)? else {
+                return Ok(None);
+            };
+            let next_h: Hash = clipboard_entry_hash(&next.content);
+            if next_h == h {
+                break;
+            }
+            (data, h) = (next, next_h);
+        }
+    }
+
+    Ok((Some(h) != last).then_some((data, h)))
+}
+
+pub fn spawn_watcher(
+    tx: Sender<ClipboardEntry>,
+    last_hash: Option<Hash>,
+    capture: Arc<RwLock<Capture>>,
+) {
     thread::spawn(move || {
+        let mut source = change_source();
+        let mut last: HashMap<Selection, Hash> = Selection::ALL
+            .into_iter()
+            .filter_map(|s| last_hash.map(|h| (s, h)))
+            .collect();
+        let mut changed: Option<Selection> = None;
         loop {
-            match read_clipboard() {
-                Ok(Some(content)) => {
-                    let h: Hash = clipboard_entry_hash(&content);
-                    if Some(h) != last_hash {
+            let capture: Capture = capture.read().map(|c| c.clone()).unwrap_or_default();
+
+            for selection in Selection::ALL {
+                if selection == Selection::Primary && !capture.track_primary {
+                    continue;
+                }
+                if changed.is_some_and(|c| c != selection) {
+                    continue;
+                }
+
+                let last_h = last.get(&selection).copied();
+                match read_new(source.as_mut(), selection, &capture, last_h) {
+                    Ok(Some((data, h))) => {
+                        last.insert(selection, h);
+
+                        let other = selection.other();
+                        if capture.track_primary
+                            && capture.sync_selections
+                            && last.get(&other) != Some(&h)
+                        {
+                            match set_clipboard(&data.content, &data.targets, other) {
+                                Ok(()) => {
+                                    last.insert(other, h);
+                                }
+                                Err(e) => eprintln!("Failed to sync selections: {e}"),
+                            }
+                        }
+
+                        let thumb = match &data.content {
+                            ClipboardContent::ImageBase64(b64) => thumbnail(b64)
+                                .inspect_err(|e| eprintln!("Failed to create a thumbnail: {e}"))
+                                .ok(),
+                            ClipboardContent::Text(_) => None,
+                        };
                         let entry: ClipboardEntry = ClipboardEntry {
                             ts: Utc::now(),
-                            content: content.clone(),
+                            content: data.content,
+                            pinned: false,
+                            source: selection,
+                            targets: data.targets,
+                            thumb,
+                            secrets: Vec::new(),
+                            expires: None,
+                            usage: Usage::default(),
                         };
                         let _ = tx.send(entry);
-                        last_hash = Some(h);
                     }
-                }
-                Ok(None) => {}
-                Err(_e) => {
-                    eprintln!("clipboard read error: {_e:?}");
+                    Ok(None) => {}
+                    Err(_e) => {
+                        eprintln!("clipboard read error: {_e:?}");
+                    }
                 }
             }
-            thread::sleep(Duration::from_millis(500));
+
+            changed = match source.wait() {
+                Ok(changed) => changed,
+                Err(e) => {
+                    eprintln!("Clipboard notifications failed, polling instead: {e}");
+                    source = Box::new(PollSource {
+                        interval: POLL_INTERVAL,
+                    });
+                    None
+                }
+            };
         }
     });
 }
@@ -1,10 +1,13 @@
 use anyhow::anyhow;
-use argon2::Argon2;
+use argon2::{Algorithm, Argon2, Params, Version};
 use chacha20poly1305::{
     XChaCha20Poly1305,
-    aead::{Aead, NewAead},
+    XNonce,
+    aead::{Aead, NewAead, Payload},
 };
 use std::fs;
+use std::time::{Duration, Instant};
+use zeroize::Zeroize;
 
 pub fn derive_save_nonce(key: &[u8; 32], base_nonce: &[u8; 24], counter: u64) -> [u8; 24] {
     let mut hasher = blake3::Hasher::new_keyed(key);
@@ -17,35 +20,205 @@ pub fn derive_save_nonce(key: &[u8; 32], base_nonce: &[u8; 24], counter: u64) ->
     nonce
 }
 
-pub fn derivate_crypto_params(passphrase: String) -> ([u8; 32], [u8; 24]) {
+pub fn derivate_crypto_params(
+    passphrase: String,
+    kdf: &KdfParams,
+) -> anyhow::Result<([u8; 32], [u8; 24])> {
     let mut key = [0u8; 32];
     let mut nonce = [0u8; 24];
-    let salt = b"saltyMcSaltface";
     let mut argon2_output = [0u8; 56];
 
-    Argon2::default()
-        .hash_password_into(passphrase.as_bytes(), salt, &mut argon2_output)
-        .expect("Failed to hash password");
+    let params = Params::new(kdf.m_cost, kdf.t_cost, kdf.p_cost, None)
+        .map_err(|err| anyhow!("Invalid KDF parameters: {}", err))?;
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), &kdf.salt, &mut argon2_output)
+        .map_err(|err| anyhow!("Failed to hash password: {}", err))?;
 
+    // The tail used to be the base nonce; it's only needed for headerless vaults now.
     key.copy_from_slice(&argon2_output[..32]);
     nonce.copy_from_slice(&argon2_output[32..56]);
-    (key, nonce)
+    argon2_output.zeroize();
+    Ok((key, nonce))
 }
 
-pub fn encrypt_data_to_file(
-    file_data: &Vec<u8>,
-    dist: &str,
+/// Compares two keys without bailing out on the first differing byte.
+pub fn keys_match(a: &[u8; 32], b: &[u8; 32]) -> bool {
+    a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// Vault layout: header | ciphertext, the whole header is the AEAD associated data.
+// Header: MAGIC | format (u8) | kdf id (u8) | m_cost, t_cost, p_cost (u32 LE)
+//         | salt_len (u8) | salt | nonce (24)
+const VAULT_MAGIC: &[u8; 4] = b"CLPV";
+const VAULT_FORMAT_VERSION: u8 = 1;
+const KDF_ARGON2ID: u8 = 1;
+const LEGACY_SALT: &[u8] = b"saltyMcSaltface";
+const SALT_LEN: usize = 16;
+
+// New vaults are tuned so unlocking takes roughly this long.
+const KDF_TARGET_TIME: Duration = Duration::from_millis(500);
+const KDF_MAX_M_COST: u32 = 64 * 1024;
+const KDF_MIN_T_COST: u32 = 2;
+const KDF_MAX_T_COST: u32 = 16;
+
+/// Argon2id parameters a vault key is derived with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KdfParams {
+    pub m_cost: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+    pub salt: Vec<u8>,
+}
+
+impl KdfParams {
+    /// What every vault used before the header existed.
+    pub fn legacy() -> Self {
+        Self {
+            m_cost: Params::DEFAULT_M_COST,
+            t_cost: Params::DEFAULT_T_COST,
+            p_cost: Params::DEFAULT_P_COST,
+            salt: LEGACY_SALT.to_vec(),
+        }
+    }
+
+    /// Parameters for a new vault: a random salt, and Argon2 costs calibrated
+    /// on this machine so that deriving the key takes about `KDF_TARGET_TIME`.
+    pub fn generate() -> anyhow::Result<Self> {
+        let mut salt = vec![0u8; SALT_LEN];
+        getrandom::fill(&mut salt).map_err(|err| anyhow!("Generating salt: {}", err))?;
+
+        let time_pass = |m_cost: u32| -> anyhow::Result<Duration> {
+            let params = Params::new(m_cost, 1, 1, None)
+                .map_err(|err| anyhow!("Invalid KDF parameters: {}", err))?;
+            let mut out = [0u8; 32];
+            let start = Instant::now();
+            Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                .hash_password_into(b"calibration", &salt, &mut out)
+                .map_err(|err| anyhow!("Calibrating KDF: {}", err))?;
+            Ok(start.elapsed())
+        };
+
+        // Prefer memory over iterations; only give memory up on slow machines.
+        let mut m_cost = KDF_MAX_M_COST;
+        let mut per_pass = time_pass(m_cost)?;
+        while per_pass * KDF_MIN_T_COST > KDF_TARGET_TIME && m_cost > Params::DEFAULT_M_COST {
+            m_cost = (m_cost / 2).max(Params::DEFAULT_M_COST);
+            per_pass = time_pass(m_cost)?;
+        }
+
+        let passes = KDF_TARGET_TIME.as_secs_f64() / per_pass.as_secs_f64().max(1e-6);
+        let t_cost = (passes as u32).clamp(KDF_MIN_T_COST, KDF_MAX_T_COST);
+
+        Ok(Self {
+            m_cost,
+            t_cost,
+            p_cost: 1,
+            salt,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VaultHeader {
+    pub kdf: KdfParams,
+    pub nonce: [u8; 24],
+}
+
+impl VaultHeader {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(44 + self.kdf.salt.len());
+        out.extend_from_slice(VAULT_MAGIC);
+        out.push(VAULT_FORMAT_VERSION);
+        out.push(KDF_ARGON2ID);
+        out.extend_from_slice(&self.kdf.m_cost.to_le_bytes());
+        out.extend_from_slice(&self.kdf.t_cost.to_le_bytes());
+        out.extend_from_slice(&self.kdf.p_cost.to_le_bytes());
+        out.push(self.kdf.salt.len() as u8);
+        out.extend_from_slice(&self.kdf.salt);
+        out.extend_from_slice(&self.nonce);
+        out
+    }
+
+    /// Parses the header at the front of `data` and returns it with its length.
+    /// `Ok(None)` means the file predates the header.
+    pub fn parse(data: &[u8]) -> anyhow::Result<Option<(Self, usize)>> {
+        if data.len() < VAULT_MAGIC.len() || &data[..VAULT_MAGIC.len()] != VAULT_MAGIC {
+            return Ok(None);
+        }
+        let truncated = || anyhow!("Vault header is truncated");
+        let mut pos = VAULT_MAGIC.len();
+        let mut take = |n: usize| -> anyhow::Result<&[u8]> {
+            let bytes = data.get(pos..pos + n).ok_or_else(truncated)?;
+            pos += n;
+            Ok(bytes)
+        };
+        let u32_at = |b: &[u8]| u32::from_le_bytes(b.try_into().unwrap());
+
+        let format = take(1)?[0];
+        if format != VAULT_FORMAT_VERSION {
+            return Err(anyhow!("Unsupported vault format {}", format));
+        }
+        let kdf_id = take(1)?[0];
+        if kdf_id != KDF_ARGON2ID {
+            return Err(anyhow!("Unsupported key derivation function {}", kdf_id));
+        }
+        let m_cost = u32_at(take(4)?);
+        let t_cost = u32_at(take(4)?);
+        let p_cost = u32_at(take(4)?);
+        let salt_len = take(1)?[0] as usize;
+        let salt = take(salt_len)?.to_vec();
+        let mut nonce = [0u8; 24];
+        nonce.copy_from_slice(take(24)?);
+
+        let header = Self {
+            kdf: KdfParams {
+                m_cost,
+                t_cost,
+                p_cost,
+                salt,
+            },
+            nonce,
+        };
+        Ok(Some((header, pos)))
+    }
+}
+
+/// Encrypts `plain` under a fresh random nonce and prepends the vault header.
+pub fn seal_vault(
+    plain: &[u8],
     key: &[u8; 32],
-    nonce: &[u8; 24],
-) -> Result<(), anyhow::Error> {
+    kdf: &KdfParams,
+) -> anyhow::Result<(VaultHeader, Vec<u8>)> {
+    let header = VaultHeader {
+        kdf: kdf.clone(),
+        nonce: random_nonce()?,
+    };
+    let aad = header.to_bytes();
     let cipher = XChaCha20Poly1305::new(key.into());
-    let encrypted_file = cipher
-        .encrypt(nonce.into(), file_data.as_ref())
-        .map_err(|err| anyhow!("Encrypting small file: {}", err))?;
+    let sealed = cipher
+        .encrypt(XNonce::from_slice(&header.nonce), Payload { msg: plain, aad: &aad })
+        .map_err(|err| anyhow!("Encrypting vault: {}", err))?;
 
-    fs::write(dist, encrypted_file)?;
+    let mut out = aad;
+    out.extend_from_slice(&sealed);
+    Ok((header, out))
+}
 
-    Ok(())
+/// Decrypts a vault written by `seal_vault`.
+pub fn open_vault(data: &[u8], key: &[u8; 32]) -> anyhow::Result<(VaultHeader, Vec<u8>)> {
+    let (header, len) =
+        VaultHeader::parse(data)?.ok_or_else(|| anyhow!("Vault has no header"))?;
+    let cipher = XChaCha20Poly1305::new(key.into());
+    let plain = cipher
+        .decrypt(
+            XNonce::from_slice(&header.nonce),
+            Payload {
+                msg: &data[len..],
+                aad: &data[..len],
+            },
+        )
+        .map_err(|err| anyhow!("Decrypting vault: {}", err))?;
+    Ok((header, plain))
 }
 
 pub fn decrypt_file(
@@ -72,3 +245,34 @@ pub fn decrypt_file(
 
     (Ok(()), decrypted_data)
 }
+
+pub fn random_nonce() -> anyhow::Result<[u8; 24]> {
+    let mut nonce = [0u8; 24];
+    getrandom::fill(&mut nonce).map_err(|err| anyhow!("Generating nonce: {}", err))?;
+    Ok(nonce)
+}
+
+/// Seals a single record as `nonce || ciphertext`, binding `aad` into the tag.
+pub fn encrypt_record(plain: &[u8], key: &[u8; 32], aad: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let cipher = XChaCha20Poly1305::new(key.into());
+    let nonce = random_nonce()?;
+    let sealed = cipher
+        .encrypt(XNonce::from_slice(&nonce), Payload { msg: plain, aad })
+        .map_err(|err| anyhow!("Encrypting record: {}", err))?;
+
+    let mut out = Vec::with_capacity(nonce.len() + sealed.len());
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&sealed);
+    Ok(out)
+}
+
+pub fn decrypt_record(frame: &[u8], key: &[u8; 32], aad: &[u8]) -> anyhow::Result<Vec<u8>> {
+    if frame.len() < 24 {
+        return Err(anyhow!("Record too short"));
+    }
+    let (nonce, sealed) = frame.split_at(24);
+    let cipher = XChaCha20Poly1305::new(key.into());
+    cipher
+        .decrypt(XNonce::from_slice(nonce), Payload { msg: sealed, aad })
+        .map_err(|err| anyhow!("Decrypting record: {}", err))
+}
@@ -0,0 +1,95 @@
+use zeroize::Zeroize;
+
+/// What the user chose in the editor.
+pub enum EditorAction {
+    /// Replace the entry with the edited text
+    Save,
+    /// Record the edited text next to the original
+    SaveAsNew,
+    Cancel,
+}
+
+/// A text entry opened for editing before it is restored.
+pub struct Editor {
+    pub key: String,
+    pub text: String,
+    /// Shown when the edit couldn't be saved
+    pub error: Option<String>,
+    focused: bool,
+}
+
+impl Editor {
+    pub fn open(key: String, text: &str) -> Self {
+        Self {
+            key,
+            text: text.to_owned(),
+            error: None,
+            focused: false,
+        }
+    }
+
+    /// Draws the editor window, returns what to do with the edit once the
+    /// user picked something.
+    pub fn show(&mut self, ctx: &egui::Context) -> Option<EditorAction> {
+        let mut open = true;
+        let mut action = None;
+        egui::Window::new("Edit entry")
+            .open(&mut open)
+            .default_size([560.0, 320.0])
+            .resizable(true)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                egui::TopBottomPanel::bottom("editor_buttons")
+                    .show_separator_line(false)
+                    .show_inside(ui, |ui| {
+                        if let Some(error) = &self.error {
+                            ui.colored_label(ui.visuals().error_fg_color, error);
+                        }
+                        ui.horizontal(|ui| {
+                            if ui.button("Save").on_hover_text("Ctrl+Enter").clicked() {
+                                action = Some(EditorAction::Save);
+                            }
+                            if ui
+                                .button("Save as new")
+                                .on_hover_text("Keep the original entry")
+                                .clicked()
+                            {
+                                action = Some(EditorAction::SaveAsNew);
+                            }
+                            if ui.button("Cancel").clicked() {
+                                action = Some(EditorAction::Cancel);
+                            }
+                        });
+                    });
+                egui::ScrollArea::vertical()
+                    .auto_shrink(false)
+                    .show(ui, |ui| {
+                        let resp = ui.add_sized(
+                            ui.available_size(),
+                            egui::TextEdit::multiline(&mut self.text)
+                                .code_editor()
+                                .desired_width(f32::INFINITY),
+                        );
+                        if !std::mem::replace(&mut self.focused, true) {
+                            resp.request_focus();
+                        }
+                        if resp.changed() {
+                            self.error = None;
+                        }
+                    });
+            });
+        if ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::Enter)) {
+            action = Some(EditorAction::Save);
+        }
+        if !open {
+            action = Some(EditorAction::Cancel);
+        }
+        action
+    }
+}
+
+impl Drop for Editor {
+    fn drop(&mut self) {
+        self.text.zeroize();
+    }
+}
@@ -0,0 +1,128 @@
+use std::ops::Range;
+
+// Scores as fzf weighs them: every matched character earns the same, word
+// starts and runs of consecutive characters earn more, gaps cost.
+const SCORE_MATCH: i32 = 16;
+const BONUS_BOUNDARY: i32 = 8;
+const BONUS_CAMEL: i32 = 7;
+const BONUS_CONSECUTIVE: i32 = 4;
+const BONUS_FIRST_CHAR_MULTIPLIER: i32 = 2;
+const PENALTY_GAP_START: i32 = 3;
+const PENALTY_GAP_EXTENSION: i32 = 1;
+
+/// How well a pattern matched a text, and where.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Match {
+    pub score: i32,
+    /// Byte ranges of the matched characters in the text
+    pub ranges: Vec<Range<usize>>,
+}
+
+/// Whether two characters are the same, ignoring case.
+pub fn eq(a: char, b: char) -> bool {
+    a == b || a.to_lowercase().eq(b.to_lowercase())
+}
+
+/// Bonus for matching the character at `i`, depending on what precedes it.
+fn bonus(chars: &[(usize, char)], i: usize) -> i32 {
+    let c = chars[i].1;
+    let Some(&(_, prev)) = i.checked_sub(1).map(|p| &chars[p]) else {
+        return BONUS_BOUNDARY;
+    };
+    if !prev.is_alphanumeric() && c.is_alphanumeric() {
+        BONUS_BOUNDARY
+    } else if prev.is_lowercase() && c.is_uppercase() || !prev.is_numeric() && c.is_numeric() {
+        BONUS_CAMEL
+    } else {
+        0
+    }
+}
+
+/// Matches `pattern` as a subsequence of `text`, ignoring case. Returns
+/// `None` unless every pattern character appears in order.
+///
+/// Like fzf's v1 algorithm, the first occurrence is found going forward,
+/// then narrowed down by going back from where it ended, so `abc` in
+/// `a_ab_abc` matches the tight `abc` rather than the scattered first `a`.
+pub fn fuzzy_match(pattern: &str, text: &str) -> Option<Match> {
+    let pattern: Vec<char> = pattern.chars().collect();
+    if pattern.is_empty() {
+        return Some(Match {
+            score: 0,
+            ranges: Vec::new(),
+        });
+    }
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+
+    // Forward: where the first full occurrence ends
+    let mut p = 0;
+    let mut end = None;
+    for (i, &(_, c)) in chars.iter().enumerate() {
+        if eq(c, pattern[p]) {
+            p += 1;
+            if p == pattern.len() {
+                end = Some(i);
+                break;
+            }
+        }
+    }
+    let end = end?;
+
+    // Backward: the latest start that still reaches `end`
+    let mut p = pattern.len();
+    let mut start = end;
+    for i in (0..=end).rev() {
+        if eq(chars[i].1, pattern[p - 1]) {
+            p -= 1;
+            if p == 0 {
+                start = i;
+                break;
+            }
+        }
+    }
+
+    let mut score = 0;
+    let mut positions = Vec::with_capacity(pattern.len());
+    let mut p = 0;
+    let mut prev: Option<usize> = None;
+    let mut run_bonus = 0;
+    for i in start..=end {
+        if p == pattern.len() {
+            break;
+        }
+        if !eq(chars[i].1, pattern[p]) {
+            continue;
+        }
+        let mut b = bonus(&chars, i);
+        match prev {
+            Some(j) if j + 1 == i => {
+                // A run keeps the bonus of its first character
+                run_bonus = run_bonus.max(b).max(BONUS_CONSECUTIVE);
+                b = run_bonus;
+            }
+            Some(j) => {
+                let gap = (i - j - 1) as i32;
+                score -= PENALTY_GAP_START + PENALTY_GAP_EXTENSION * (gap - 1);
+                run_bonus = b;
+            }
+            None => {
+                b *= BONUS_FIRST_CHAR_MULTIPLIER;
+                run_bonus = b;
+            }
+        }
+        score += SCORE_MATCH + b;
+        positions.push(i);
+        prev = Some(i);
+        p += 1;
+    }
+
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for i in positions {
+        let (at, c) = chars[i];
+        match ranges.last_mut() {
+            Some(r) if r.end == at => r.end = at + c.len_utf8(),
+            _ => ranges.push(at..at + c.len_utf8()),
+        }
+    }
+    Some(Match { score, ranges })
+}
@@ -1,6 +1,10 @@
+use anyhow::anyhow;
 use arboard::ImageData;
 use base64::{Engine as _, engine::general_purpose};
+use chrono::{DateTime, Local, Utc};
 use png::{ColorType, Decoder, Encoder};
+use std::fs;
+use std::path::PathBuf;
 
 pub fn image_to_base64(img: &ImageData) -> String {
     let mut png_bytes: Vec<u8> = Vec::new();
@@ -27,3 +31,51 @@ pub fn base64_to_imagedata(b64: &str) -> anyhow::Result<ImageData<'_>> {
         bytes: buf[..info.buffer_size()].to_vec().into(),
     })
 }
+
+/// Re-encodes PNG data from another application the same way arboard images
+/// are encoded, so the same picture always hashes to the same entry.
+pub fn png_to_base64(png_bytes: &[u8]) -> anyhow::Result<String> {
+    let rgba = image::load_from_memory_with_format(png_bytes, image::ImageFormat::Png)?.to_rgba8();
+    let img = ImageData {
+        width: rgba.width() as usize,
+        height: rgba.height() as usize,
+        bytes: rgba.into_raw().into(),
+    };
+    Ok(image_to_base64(&img))
+}
+
+/// Largest thumbnail shown in the history list, in pixels.
+pub const THUMB_MAX_W: u32 = 512;
+pub const THUMB_MAX_H: u32 = 256;
+
+/// Downscales a base64 PNG to fit `THUMB_MAX_W` x `THUMB_MAX_H`, returned
+/// as base64 PNG again.
+pub fn thumbnail(b64: &str) -> anyhow::Result<String> {
+    let bytes: Vec<u8> = general_purpose::STANDARD.decode(b64)?;
+    let img = image::load_from_memory_with_format(&bytes, image::ImageFormat::Png)?;
+    let thumb = if img.width() > THUMB_MAX_W || img.height() > THUMB_MAX_H {
+        img.thumbnail(THUMB_MAX_W, THUMB_MAX_H)
+    } else {
+        img
+    };
+    let mut png_bytes: Vec<u8> = Vec::new();
+    thumb.write_to(
+        &mut std::io::Cursor::new(&mut png_bytes),
+        image::ImageFormat::Png,
+    )?;
+    Ok(general_purpose::STANDARD.encode(png_bytes))
+}
+
+/// Writes a base64 PNG into the pictures directory, named after the time it
+/// was copied. Returns where it went.
+pub fn save_png(b64: &str, ts: DateTime<Utc>) -> anyhow::Result<PathBuf> {
+    let dir = dirs_next::picture_dir()
+        .or_else(dirs_next::home_dir)
+        .ok_or_else(|| anyhow!("No pictures directory"))?;
+    let name = ts
+        .with_timezone(&Local)
+        .format("ClipVault-%Y%m%d-%H%M%S.png");
+    let path = dir.join(name.to_string());
+    fs::write(&path, general_purpose::STANDARD.decode(b64)?)?;
+    Ok(path)
+}
@@ -0,0 +1,247 @@
+use anyhow::{Result, anyhow};
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use zeroize::Zeroize;
+
+use crate::crypto::{decrypt_record, encrypt_record};
+use crate::paths::history_path;
+use crate::types::LogRec;
+
+// File layout: MAGIC | gen_len (u8) | gen | frames...
+// Frame layout: len (u32 LE) | nonce (24) | ciphertext
+const MAGIC: &[u8; 4] = b"CVJ1";
+const MAX_FRAME_LEN: usize = 256 * 1024 * 1024;
+
+pub fn journal_path() -> PathBuf {
+    history_path().with_extension("journal")
+}
+
+/// Append-only log of `LogRec`s written between two snapshots.
+///
+/// Every record is sealed on its own, with the generation of the snapshot it
+/// extends as associated data, so a journal left over from an older snapshot
+/// is never replayed on top of a newer one.
+pub struct Journal {
+    file: File,
+    key: [u8; 32],
+    generation: Vec<u8>,
+    records: usize,
+    bytes: u64,
+}
+
+impl Journal {
+    /// Opens the journal for `generation`, returning it together with the
+    /// records that are already in it. A journal for another generation is
+    /// discarded, and a torn tail is cut off.
+    ///
+    /// A complete record that can't be read fails instead: cutting the
+    /// journal there would lose every record after it, and a record this
+    /// build doesn't know was likely written by a newer one.
+    pub fn open(key: [u8; 32], generation: &[u8]) -> Result<(Self, Vec<LogRec>)> {
+        let path = journal_path();
+        // Any other failure has to surface, the journal would be replaced
+        let data = match fs::read(&path) {
+            Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+            data => data?,
+        };
+
+        let header_len = MAGIC.len() + 1 + generation.len();
+        let same_generation = data.len() >= header_len
+            && &data[..MAGIC.len()] == MAGIC
+            && data[MAGIC.len()] as usize == generation.len()
+            && &data[MAGIC.len() + 1..header_len] == generation;
+
+        if !same_generation {
+            let journal = Self::create(key, generation)?;
+            return Ok((journal, Vec::new()));
+        }
+
+        let mut recs = Vec::new();
+        let mut pos = header_len;
+        while pos + 4 <= data.len() {
+            let len = u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
+            let start = pos + 4;
+            // No record is ever this long, so unless nothing follows the
+            // length it is corruption, not a torn write
+            if len > MAX_FRAME_LEN && start < data.len() {
+                return Err(anyhow!("Journal record at byte {pos} has a corrupt length"));
+            }
+            if start + len > data.len() {
+                break;
+            }
+            let plain = match decrypt_record(&data[start..start + len], &key, generation) {
+                Ok(plain) => plain,
+                // The last frame may be one whose length made it to disk
+                // before its contents did
+                Err(_) if start + len == data.len() => break,
+                Err(e) => return Err(anyhow!("Journal record at byte {pos} is corrupt: {e}")),
+            };
+            let rec = serde_json::from_slice::<LogRec>(&plain).map_err(|e| {
+                anyhow!("Journal record at byte {pos} is unknown, from a newer ClipVault? {e}")
+            })?;
+            recs.push(rec);
+            pos = start + len;
+        }
+
+        let mut file = OpenOptions::new().write(true).open(&path)?;
+        file.set_len(pos as u64)?;
+        file.seek(SeekFrom::End(0))?;
+
+        let journal = Self {
+            file,
+            key,
+            generation: generation.to_vec(),
+            records: recs.len(),
+            bytes: pos as u64,
+        };
+        Ok((journal, recs))
+    }
+
+    /// Starts an empty journal for `generation`, replacing any existing one.
+    pub fn create(key: [u8; 32], generation: &[u8]) -> Result<Self> {
+        let path = journal_path();
+        let tmp = path.with_extension("journal.tmp");
+
+        let gen_len = u8::try_from(generation.len()).map_err(|_| anyhow!("Generation too long"))?;
+        let mut header = Vec::with_capacity(MAGIC.len() + 1 + generation.len());
+        header.extend_from_slice(MAGIC);
+        header.push(gen_len);
+        header.extend_from_slice(generation);
+
+        {
+            let mut f = File::create(&tmp)?;
+            f.write_all(&header)?;
+            f.sync_all()?;
+        }
+        fs::rename(&tmp, &path)?;
+
+        let file = OpenOptions::new().append(true).open(&path)?;
+
+        Ok(Self {
+            file,
+            key,
+            generation: generation.to_vec(),
+            records: 0,
+            bytes: header.len() as u64,
+        })
+    }
+
+    pub fn append(&mut self, rec: &LogRec) -> Result<()> {
+        let json = serde_json::to_vec(rec)?;
+        let frame = encrypt_record(&json, &self.key, &self.generation)?;
+        let len = u32::try_from(frame.len()).map_err(|_| anyhow!("Record too large"))?;
+
+        let mut buf = Vec::with_capacity(4 + frame.len());
+        buf.extend_from_slice(&len.to_le_bytes());
+        buf.extend_from_slice(&frame);
+        self.file.write_all(&buf)?;
+        self.file.sync_data()?;
+
+        self.records += 1;
+        self.bytes += buf.len() as u64;
+        Ok(())
+    }
+
+    pub fn records(&self) -> usize {
+        self.records
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+}
+
+impl Drop for Journal {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paths::scratch_history;
+
+    const KEY: [u8; 32] = [7; 32];
+    const GENERATION: &[u8] = b"generation";
+
+    fn remove(key: &str) -> LogRec {
+        LogRec::Remove {
+            key: key.to_owned(),
+        }
+    }
+
+    #[test]
+    fn torn_tail_is_cut_off() {
+        let _history = scratch_history("journal-torn");
+        let mut journal = Journal::create(KEY, GENERATION).unwrap();
+        journal.append(&remove("a")).unwrap();
+        let intact = journal.bytes();
+        journal.append(&remove("b")).unwrap();
+        drop(journal);
+
+        let path = journal_path();
+        let mut data = fs::read(&path).unwrap();
+        data.truncate(data.len() - 3);
+        fs::write(&path, data).unwrap();
+
+        let (journal, recs) = Journal::open(KEY, GENERATION).unwrap();
+        assert_eq!(recs.len(), 1);
+        assert_eq!(journal.bytes(), intact);
+        assert_eq!(fs::metadata(&path).unwrap().len(), intact);
+    }
+
+    #[test]
+    fn corrupt_length_is_kept() {
+        let _history = scratch_history("journal-length");
+        let mut journal = Journal::create(KEY, GENERATION).unwrap();
+        journal.append(&remove("a")).unwrap();
+        let corrupt = journal.bytes() as usize;
+        journal.append(&remove("b")).unwrap();
+        journal.append(&remove("c")).unwrap();
+        drop(journal);
+
+        // The second frame claims to be bigger than any record can be,
+        // with the rest of the journal still following it
+        let path = journal_path();
+        let mut data = fs::read(&path).unwrap();
+        data[corrupt..corrupt + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        fs::write(&path, &data).unwrap();
+
+        assert!(Journal::open(KEY, GENERATION).is_err());
+        assert_eq!(fs::read(&path).unwrap(), data);
+    }
+
+    #[test]
+    fn unreadable_journal_is_kept() {
+        let _history = scratch_history("journal-unreadable");
+        // Reading a directory fails with something other than NotFound
+        fs::create_dir_all(journal_path()).unwrap();
+        assert!(Journal::open(KEY, GENERATION).is_err());
+        assert!(journal_path().is_dir());
+    }
+
+    #[test]
+    fn unknown_record_is_kept() {
+        let _history = scratch_history("journal-unknown");
+        let mut journal = Journal::create(KEY, GENERATION).unwrap();
+        journal.append(&remove("a")).unwrap();
+
+        // A complete record of a kind this build doesn't know
+        let frame = encrypt_record(br#"{"type":"future","key":"b"}"#, &KEY, GENERATION).unwrap();
+        let len = (frame.len() as u32).to_le_bytes();
+        journal
+            .file
+            .write_all(&[&len[..], &frame].concat())
+            .unwrap();
+
+        journal.append(&remove("c")).unwrap();
+        drop(journal);
+
+        let path = journal_path();
+        let before = fs::read(&path).unwrap();
+        assert!(Journal::open(KEY, GENERATION).is_err());
+        assert_eq!(fs::read(&path).unwrap(), before);
+    }
+}
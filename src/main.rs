@@ -2,22 +2,32 @@
 mod assets;
 mod clip;
 mod crypto;
+mod editor;
+mod fuzzy;
 mod img;
+mod journal;
+mod menu;
 mod parser;
 mod paths;
 mod singleton;
 mod storage;
+mod thumbs;
 mod tray;
 mod types;
 mod ui;
 mod prefs;
+mod preview;
+mod query;
+mod secrets;
+#[cfg(target_os = "linux")]
+mod wayland;
+#[cfg(target_os = "linux")]
+mod x11;
 
 use crate::assets::{ICON_TRAY, get_bytes, icon_data_from_png};
-use crate::clip::{clipboard_entry_hash, spawn_watcher};
 use crate::parser::cli_args_handler;
 use crate::singleton::setup_single_instance;
-use crate::storage::Store;
-use crate::types::{HotkeyMsg, UnlockResult};
+use crate::types::{HotkeyMsg, InstanceMsg};
 
 use crossbeam::channel;
 use global_hotkey::{
@@ -27,16 +37,25 @@ use global_hotkey::{
 
 use std::time::{Duration, Instant};
 
-fn unencrypted_main(
-    key: [u8; 32],
-    nonce: [u8; 24],
-    activate_rx: crossbeam::channel::Receiver<()>,
-) -> anyhow::Result<()> {
+fn run(instance_rx: crossbeam::channel::Receiver<InstanceMsg>) -> anyhow::Result<()> {
     let (hk_tx, hk_rx) = channel::unbounded::<HotkeyMsg>();
     std::thread::spawn(move || {
-        let global_hotkey_manager = GlobalHotKeyManager::new().expect("hotkey manager");
-        let global_hotkey = HotKey::new(Some(Modifiers::SUPER), Code::KeyV);
-        global_hotkey_manager.register(global_hotkey).expect("register hotkey");
+        // Wayland compositors don't let clients grab keys, bind `clipvault --toggle` there
+        let global_hotkey_manager = match GlobalHotKeyManager::new() {
+            Ok(manager) => manager,
+            Err(e) => {
+                eprintln!("Global hotkeys unavailable: {e}");
+                return;
+            }
+        };
+        let toggle_hotkey = HotKey::new(Some(Modifiers::SUPER), Code::KeyV);
+        let lock_hotkey = HotKey::new(Some(Modifiers::SUPER | Modifiers::SHIFT), Code::KeyL);
+        if let Err(e) = global_hotkey_manager.register(toggle_hotkey) {
+            eprintln!("Failed to register toggle hotkey: {e}");
+        }
+        if let Err(e) = global_hotkey_manager.register(lock_hotkey) {
+            eprintln!("Failed to register lock hotkey: {e}");
+        }
 
         let global_hotkey_rx = GlobalHotKeyEvent::receiver();
 
@@ -44,34 +63,31 @@ fn unencrypted_main(
         let mut last = Instant::now() - Duration::from_millis(500);
 
         loop {
-            if let Ok(ev) = global_hotkey_rx.recv() {
-                if ev.state == HotKeyState::Pressed && last.elapsed() > Duration::from_millis(250) {
-                    let _ = hk_tx.send(HotkeyMsg::ToggleWindow);
-                    last = Instant::now();
-                }
+            if let Ok(ev) = global_hotkey_rx.recv()
+                && ev.state == HotKeyState::Pressed
+                && last.elapsed() > Duration::from_millis(250)
+            {
+                let msg = if ev.id == lock_hotkey.id() {
+                    HotkeyMsg::Lock
+                } else {
+                    HotkeyMsg::ToggleWindow
+                };
+                let _ = hk_tx.send(msg);
+                last = Instant::now();
             }
         }
     });
 
-    let store = Store::open_or_create(key, nonce)?;
-    let last_hash = store
-        .entries()
-        .last()
-        .map(|e| clipboard_entry_hash(&e.content));
-
-    let (tx, rx) = crossbeam::channel::unbounded();
-    spawn_watcher(tx, last_hash);
-
     let icon = get_bytes(ICON_TRAY)
         .and_then(|b| icon_data_from_png(&b))
         .unwrap();
 
+    // Starts on the passphrase prompt; `ClipApp` resizes it once unlocked
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
-            .with_inner_size([512.0, 600.0])
+            .with_inner_size([300.0, 118.0])
             .with_resizable(false)
-            .with_decorations(false)
-            .with_visible(false)
+            .with_visible(true)
             .with_icon(icon),
         vsync: true,
         multisampling: 0,
@@ -84,7 +100,6 @@ fn unencrypted_main(
     let tray_clone = tray.clone();
 
     let p = prefs::load();
-    let auto_launch = p.auto_launch;
 
     let res = eframe::run_native(
         "ClipVault",
@@ -92,11 +107,9 @@ fn unencrypted_main(
         Box::new(move |_cc| {
             Ok::<Box<dyn eframe::App>, _>(Box::new(ui::ClipApp::new(
                 tray_clone,
-                rx,
-                store,
                 hk_rx,
-                activate_rx,
-                auto_launch
+                instance_rx,
+                p,
             )))
         }),
     );
@@ -107,70 +120,13 @@ fn unencrypted_main(
     Ok(())
 }
 
-fn encrypted_main() -> anyhow::Result<([u8; 32], [u8; 24])> {
-    let (tx, rx) = channel::bounded::<UnlockResult>(1);
-
-    let icon = get_bytes(ICON_TRAY)
-        .and_then(|b| icon_data_from_png(&b))
-        .unwrap();
-
-    let options = eframe::NativeOptions {
-        viewport: egui::ViewportBuilder::default()
-            .with_inner_size([300.0, 118.0])
-            .with_resizable(false)
-            .with_visible(true)
-            .with_icon(icon),
-        vsync: true,
-        multisampling: 0,
-        depth_buffer: 0,
-        stencil_buffer: 0,
-        ..Default::default()
-    };
-
-    let res = eframe::run_native(
-        "ClipVault",
-        options,
-        Box::new(move |_cc| Ok::<Box<dyn eframe::App>, _>(Box::new(ui::ClipAppLocked::new(tx)))),
-    );
-
-    if let Err(e) = res {
-        eprintln!("eframe error: {e}");
-    }
-
-    let outcome = rx
-        .recv_timeout(Duration::from_millis(50))
-        .unwrap_or(UnlockResult::Cancelled);
-
-    match outcome {
-        UnlockResult::Unlocked { key, nonce } => {
-            Ok((key, nonce))
-        }
-        UnlockResult::Cancelled => {
-            Err(anyhow::anyhow!("Failed to unlock ClipVault: {outcome:?}"))
-        }
-    }
-}
-
 fn main() -> anyhow::Result<()> {
     cli_args_handler();
 
-    let (activate_tx, activate_rx) = crossbeam::channel::unbounded();
-    if !setup_single_instance(activate_tx.clone()) {
+    let (instance_tx, instance_rx) = crossbeam::channel::unbounded();
+    if !setup_single_instance(instance_tx) {
         return Ok(());
     }
 
-    let crypto_params = encrypted_main();
-    match crypto_params {
-        Ok((key, nonce)) => {
-            if let Err(e) = unencrypted_main(key, nonce, activate_rx) {
-                eprintln!("Error in unencrypted main: {e}");
-                return Err(e);
-            }
-        }
-        Err(e) => {
-            return anyhow::Result::Err(anyhow::anyhow!("Failed to decrypt history: {e}"));
-        }
-    }
-
-    Ok(())
+    run(instance_rx)
 }
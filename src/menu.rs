@@ -0,0 +1,142 @@
+use base64::{Engine as _, engine::general_purpose};
+use std::path::{Path, PathBuf};
+
+use crate::types::{ClipboardContent, ClipboardEntry};
+
+/// An action picked from the context menu of a history row.
+pub enum RowAction {
+    Copy,
+    /// Copy without the rich formats
+    CopyPlain,
+    Pin(bool),
+    Delete,
+    Edit,
+    SaveImage,
+    CopyDataUri,
+    OpenUrl(String),
+    /// Open the directory, or the one holding the file, in the file manager
+    OpenPath(PathBuf),
+}
+
+/// Fills the context menu of a row, with the actions that fit its content
+/// enabled. `masked` is set while a masked entry isn't revealed.
+pub fn row_menu(ui: &mut egui::Ui, entry: &ClipboardEntry, masked: bool) -> Option<RowAction> {
+    let mut action = None;
+    let mut item = |ui: &mut egui::Ui, enabled: bool, label: &str, a: RowAction| {
+        if ui.add_enabled(enabled, egui::Button::new(label)).clicked() {
+            action = Some(a);
+        }
+    };
+
+    let text = match &entry.content {
+        ClipboardContent::Text(t) => Some(t.as_str()),
+        ClipboardContent::ImageBase64(_) => None,
+    };
+    let image = text.is_none();
+    let url = text.filter(|_| !masked).and_then(url_in);
+    let path = text.filter(|_| !masked).and_then(|_| path_in(entry));
+
+    item(ui, true, "Copy", RowAction::Copy);
+    item(
+        ui,
+        text.is_some() && !entry.targets.is_empty(),
+        "Copy as plain text",
+        RowAction::CopyPlain,
+    );
+    if image {
+        item(ui, true, "Copy as PNG data URI", RowAction::CopyDataUri);
+    }
+    ui.separator();
+    item(
+        ui,
+        true,
+        if entry.pinned { "Unpin" } else { "Pin" },
+        RowAction::Pin(!entry.pinned),
+    );
+    item(ui, text.is_some() && !masked, "Edit", RowAction::Edit);
+    item(ui, true, "Delete", RowAction::Delete);
+    ui.separator();
+    if image {
+        item(ui, true, "Save to Pictures", RowAction::SaveImage);
+    } else {
+        item(
+            ui,
+            url.is_some(),
+            "Open URL",
+            RowAction::OpenUrl(url.unwrap_or_default().to_owned()),
+        );
+        item(
+            ui,
+            path.is_some(),
+            "Open in file manager",
+            RowAction::OpenPath(path.unwrap_or_default()),
+        );
+    }
+    action
+}
+
+/// The URL a copied text consists of, if it is nothing else.
+pub fn url_in(text: &str) -> Option<&str> {
+    let text = text.trim();
+    let scheme_ok = ["http://", "https://", "ftp://", "mailto:"]
+        .iter()
+        .any(|s| text.starts_with(s));
+    (scheme_ok && text.len() > 8 && !text.contains(char::is_whitespace)).then_some(text)
+}
+
+/// The existing file or directory an entry names: the first file of a copy
+/// from a file manager, or a text that is a single absolute path.
+fn path_in(entry: &ClipboardEntry) -> Option<PathBuf> {
+    let uris = entry
+        .targets
+        .iter()
+        .find(|t| t.mime == "text/uri-list")
+        .and_then(|t| general_purpose::STANDARD.decode(&t.data).ok())
+        .and_then(|b| String::from_utf8(b).ok());
+    let line = match (&uris, &entry.content) {
+        (Some(uris), _) => uris.lines().find(|l| !l.starts_with('#'))?,
+        (None, ClipboardContent::Text(t)) if !t.trim().contains('\n') => t,
+        _ => return None,
+    };
+    let line = line.trim();
+
+    let path = if let Some(p) = line.strip_prefix("file://") {
+        PathBuf::from(percent_decode(p))
+    } else if let Some(p) = line.strip_prefix("~/") {
+        dirs_next::home_dir()?.join(p)
+    } else {
+        PathBuf::from(line)
+    };
+    (path.is_absolute() && path.exists()).then_some(path)
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                out.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// The directory to show for `path`: itself, or the one holding it.
+pub fn folder_of(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !path.is_dir() => parent,
+        _ => path,
+    }
+}
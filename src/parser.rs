@@ -1,12 +1,21 @@
 use std::env;
 use std::process::exit;
 
-use crate::storage::Store;
+use crate::crypto::derivate_crypto_params;
+use crate::paths::history_path;
+use crate::prefs;
+use crate::singleton::{instance_running, send_to_instance};
+use crate::storage::{NewPassphrase, Store, vault_kdf};
+
+use zeroize::Zeroize;
 
 pub enum CliArgs {
     NoArguments,
     Help,
     CleanHistory,
+    ChangePassphrase,
+    Lock,
+    Toggle,
     Unknown,
 }
 
@@ -18,6 +27,9 @@ fn cli_args_parser() -> anyhow::Result<CliArgs> {
         match args[1].as_str() {
             "--help" | "-h" => Ok(CliArgs::Help),
             "--clean-history" | "-c" => Ok(CliArgs::CleanHistory),
+            "--change-passphrase" | "-p" => Ok(CliArgs::ChangePassphrase),
+            "--lock" | "-l" => Ok(CliArgs::Lock),
+            "--toggle" | "-t" => Ok(CliArgs::Toggle),
             _ => Ok(CliArgs::Unknown),
         }
     } else {
@@ -25,6 +37,36 @@ fn cli_args_parser() -> anyhow::Result<CliArgs> {
     }
 }
 
+fn change_passphrase() -> anyhow::Result<()> {
+    if !history_path().exists() {
+        return Err(anyhow::anyhow!("No vault found, start ClipVault to create one"));
+    }
+    // The running instance would overwrite the new vault with its old key
+    if instance_running() {
+        return Err(anyhow::anyhow!("Quit the running ClipVault first"));
+    }
+
+    let mut old = rpassword::prompt_password("Current passphrase: ")?;
+    let kdf = vault_kdf()?;
+    let (key, nonce) = derivate_crypto_params(old.clone(), &kdf)?;
+    let mut store = Store::open_or_create(key, nonce, kdf, prefs::load().retention)
+        .map_err(|_| anyhow::anyhow!("Wrong passphrase"))?;
+
+    let mut new = rpassword::prompt_password("New passphrase: ")?;
+    let mut confirm = rpassword::prompt_password("Repeat new passphrase: ")?;
+    let res = if new != confirm {
+        Err(anyhow::anyhow!("Passphrases don't match"))
+    } else {
+        // Opening the vault already proved the current passphrase
+        NewPassphrase::derive(None, &new).and_then(|new| store.change_passphrase(&new))
+    };
+
+    old.zeroize();
+    new.zeroize();
+    confirm.zeroize();
+    res
+}
+
 pub fn cli_args_handler() {
     let cli_args: CliArgs = cli_args_parser().unwrap();
     match cli_args {
@@ -35,9 +77,13 @@ pub fn cli_args_handler() {
             println!("Usage:");
             println!("  clipvault -h or --help    Show this help message");
             println!("  clipvault -c or --clean-history     Clean the clipboard history");
+            println!("  clipvault -p or --change-passphrase Change the vault passphrase");
+            println!("  clipvault -l or --lock              Lock the running ClipVault");
+            println!("  clipvault -t or --toggle            Show or hide the running ClipVault");
             println!();
             println!("Hotkey:");
             println!("  Super + V                 Toggle clipboard history window");
+            println!("  Super + Shift + L         Lock ClipVault");
             exit(0);
         }
         CliArgs::CleanHistory => {
@@ -45,6 +91,28 @@ pub fn cli_args_handler() {
             Store::clean_history();
             exit(0);
         }
+        CliArgs::ChangePassphrase => {
+            if let Err(e) = change_passphrase() {
+                eprintln!("Passphrase not changed: {e}");
+                exit(1);
+            }
+            println!("Passphrase changed.");
+            exit(0);
+        }
+        CliArgs::Lock => {
+            if !send_to_instance(b"LOCK") {
+                eprintln!("ClipVault is not running");
+                exit(1);
+            }
+            exit(0);
+        }
+        CliArgs::Toggle => {
+            if !send_to_instance(b"TOGG") {
+                eprintln!("ClipVault is not running");
+                exit(1);
+            }
+            exit(0);
+        }
         CliArgs::Unknown => {
             println!("Unknown argument");
             exit(1);
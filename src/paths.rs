@@ -20,3 +20,18 @@ pub fn history_path() -> PathBuf {
     let _ = std::fs::create_dir_all(&dir);
     dir.join(".clipvault_clipboard.json")
 }
+
+/// Points the history at an empty scratch directory. Tests share the
+/// process environment, so the variable stays theirs while the returned
+/// guard is held.
+#[cfg(test)]
+pub fn scratch_history(name: &str) -> std::sync::MutexGuard<'static, ()> {
+    static LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    let guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    let dir = std::env::temp_dir().join(format!("clipvault-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    // SAFETY: every test setting the variable holds the lock
+    unsafe { std::env::set_var("CLIPVAULT_HISTORY", dir.join("history.json")) };
+    guard
+}
@@ -1,10 +1,190 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::{fs, path::PathBuf};
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use crate::secrets::{Detector, SecretAction};
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct Prefs {
     pub auto_launch: bool,
+    pub retention: Retention,
+    /// Minutes without interaction before the vault locks itself, `0` disables it.
+    pub lock_after_idle_mins: u32,
+    pub capture: Capture,
+    pub secrets: Secrets,
+    pub auto_clear: AutoClear,
+    pub paste: Paste,
+    /// Match the words of the filter loosely and rank the results by how well they match.
+    pub fuzzy_filter: bool,
+    pub sort: SortOrder,
+}
+
+/// Order of the unpinned entries in the history.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    /// Last copied or restored first
+    #[default]
+    Recency,
+    /// Most used first, see `ClipboardEntry::frecency`
+    Frecency,
+}
+
+impl SortOrder {
+    pub fn label(self) -> &'static str {
+        match self {
+            SortOrder::Recency => "Recent",
+            SortOrder::Frecency => "Frequent",
+        }
+    }
+}
+
+/// What the clipboard watcher records. The watcher thread reads its own copy,
+/// so changes apply without restarting it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Capture {
+    /// Also record the PRIMARY selection (mouse selections), X11 and Wayland only.
+    pub track_primary: bool,
+    /// Copy every new CLIPBOARD or PRIMARY content into the other selection.
+    pub sync_selections: bool,
+    /// Keep the other formats a copy is offered in (HTML, RTF, URI lists, SVG, ...)
+    /// next to its text or image, so restoring it pastes the same way.
+    pub rich_targets: bool,
+    /// Largest single extra format kept, in KB. `0` means no limit.
+    pub max_target_kb: u32,
+    /// Total size of the extra formats kept per entry, in KB. `0` means no limit.
+    pub max_targets_kb: u32,
+    /// Skip copies that password managers mark as secret.
+    pub respect_secret_hints: bool,
+    /// Applications whose copies are never recorded, matched against the
+    /// WM_CLASS, process name or Wayland app id of the selection owner.
+    pub ignored_apps: Vec<String>,
+}
+
+impl Default for Capture {
+    fn default() -> Self {
+        Self {
+            track_primary: false,
+            sync_selections: false,
+            rich_targets: true,
+            max_target_kb: 1024,
+            max_targets_kb: 4096,
+            respect_secret_hints: true,
+            ignored_apps: ["keepassxc", "keepass2", "1password", "bitwarden"]
+                .map(String::from)
+                .to_vec(),
+        }
+    }
+}
+
+/// What to do with copies that contain credentials, see `secrets::scan`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Secrets {
+    /// Per detector action, detectors missing here use their default.
+    pub actions: BTreeMap<Detector, SecretAction>,
+    /// How long entries with an expiring secret stay in the history.
+    pub expire_after_mins: u32,
+}
+
+impl Default for Secrets {
+    fn default() -> Self {
+        Self {
+            actions: Detector::ALL.map(|d| (d, d.default_action())).into(),
+            expire_after_mins: 10,
+        }
+    }
+}
+
+impl Secrets {
+    pub fn action(&self, d: Detector) -> SecretAction {
+        self.actions.get(&d).copied().unwrap_or(d.default_action())
+    }
+
+    /// Whether an entry the detectors `found` fired on is shown masked.
+    pub fn masks(&self, found: &[Detector]) -> bool {
+        found.iter().any(|d| self.action(*d) == SecretAction::Mask)
+    }
+}
+
+/// Pasting a selected entry straight into the window that was focused
+/// before ClipVault opened.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Paste {
+    pub on_select: bool,
+    /// WM_CLASS names of terminals, which paste with Ctrl+Shift+V
+    pub terminals: Vec<String>,
+}
+
+impl Default for Paste {
+    fn default() -> Self {
+        Self {
+            on_select: false,
+            terminals: [
+                "xterm",
+                "URxvt",
+                "Alacritty",
+                "kitty",
+                "gnome-terminal-server",
+                "konsole",
+                "xfce4-terminal",
+                "Tilix",
+                "Terminator",
+                "org.wezfurlong.wezterm",
+                "st-256color",
+            ]
+            .map(String::from)
+            .to_vec(),
+        }
+    }
+}
+
+/// Which restored entries are taken off the system clipboard again.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ClearScope {
+    Never,
+    /// Entries in which a secret was detected
+    Secrets,
+    All,
+}
+
+/// Clearing the system clipboard a while after restoring an entry, the way
+/// password managers do.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct AutoClear {
+    pub scope: ClearScope,
+    pub after_secs: u32,
+    /// Put back what was on the clipboard before the restore instead of
+    /// leaving it empty.
+    pub restore_previous: bool,
+}
+
+impl Default for AutoClear {
+    fn default() -> Self {
+        Self {
+            scope: ClearScope::Secrets,
+            after_secs: 30,
+            restore_previous: true,
+        }
+    }
+}
+
+/// Limits applied to the history, oldest entries are evicted first.
+/// A value of `0` means no limit.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(default)]
+pub struct Retention {
+    pub max_entries: usize,
+    pub max_age_days: u32,
+    pub max_total_mb: u32,
+    pub max_text_entries: usize,
+    pub max_image_entries: usize,
+    pub max_image_mb: u32,
 }
 
 fn cfg_app_dir() -> Option<PathBuf> {
@@ -0,0 +1,183 @@
+use chrono::{DateTime, Local, Utc};
+
+use crate::img::base64_to_imagedata;
+use crate::types::{ClipboardContent, ClipboardEntry};
+
+const MIN_ZOOM: f32 = 0.05;
+const MAX_ZOOM: f32 = 16.0;
+
+/// An entry opened in full: all of its text with line numbers, or its image
+/// at full resolution with zoom and pan.
+pub struct Preview {
+    pub key: String,
+    // Full resolution, decoded only for as long as the preview is open
+    texture: Option<egui::TextureHandle>,
+    // `None` fits the image into the window
+    zoom: Option<f32>,
+    pan: egui::Vec2,
+}
+
+impl Preview {
+    pub fn open(ctx: &egui::Context, key: String, entry: &ClipboardEntry) -> Self {
+        let texture = match &entry.content {
+            ClipboardContent::ImageBase64(b64) => match base64_to_imagedata(b64) {
+                Ok(img) => Some(ctx.load_texture(
+                    "preview",
+                    egui::ColorImage::from_rgba_unmultiplied([img.width, img.height], &img.bytes),
+                    egui::TextureOptions::LINEAR,
+                )),
+                Err(e) => {
+                    eprintln!("Failed to decode the image for the preview: {e}");
+                    None
+                }
+            },
+            ClipboardContent::Text(_) => None,
+        };
+        Self {
+            key,
+            texture,
+            zoom: None,
+            pan: egui::Vec2::ZERO,
+        }
+    }
+
+    /// Draws the preview window, returns `false` once it was closed.
+    /// `masked` hides text until the entry is revealed in the list.
+    pub fn show(&mut self, ctx: &egui::Context, entry: &ClipboardEntry, masked: bool) -> bool {
+        let mut open = true;
+        egui::Window::new("Preview")
+            .open(&mut open)
+            .default_size([640.0, 480.0])
+            .resizable(true)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label(egui::RichText::new(usage_text(entry)).weak());
+                match &entry.content {
+                    ClipboardContent::Text(_) if masked => {
+                        ui.label("This entry contains a secret, reveal it with 👁 to preview it.");
+                    }
+                    ClipboardContent::Text(t) => text_ui(ui, t),
+                    ClipboardContent::ImageBase64(_) => match &self.texture {
+                        Some(tex) => self.image_ui(ui, tex.clone()),
+                        None => {
+                            ui.label("The image couldn't be decoded.");
+                        }
+                    },
+                }
+            });
+        open
+    }
+
+    fn image_ui(&mut self, ui: &mut egui::Ui, tex: egui::TextureHandle) {
+        let [w, h] = tex.size();
+        let img_size = egui::vec2(w as f32, h as f32);
+
+        let mut fit = false;
+        let mut actual = false;
+        ui.horizontal(|ui| {
+            ui.label(format!("{w} × {h} px"));
+            ui.separator();
+            fit = ui.button("Fit").clicked();
+            actual = ui.button("100%").clicked();
+            if let Some(zoom) = self.zoom {
+                ui.label(format!("{:.0}%", zoom * 100.0));
+            }
+        });
+
+        let (area, resp) = ui.allocate_exact_size(ui.available_size(), egui::Sense::drag());
+        let fit_zoom = (area.width() / img_size.x)
+            .min(area.height() / img_size.y)
+            .min(1.0);
+        if fit {
+            self.zoom = None;
+            self.pan = egui::Vec2::ZERO;
+        }
+        if actual {
+            self.zoom = Some(1.0);
+            self.pan = egui::Vec2::ZERO;
+        }
+        let mut zoom = self.zoom.unwrap_or(fit_zoom);
+
+        if resp.hovered() {
+            let (scroll, pinch, pointer) = ui.input(|i| {
+                (
+                    i.smooth_scroll_delta.y,
+                    i.zoom_delta(),
+                    i.pointer.hover_pos(),
+                )
+            });
+            let factor = pinch * (scroll * 0.002).exp();
+            if factor != 1.0 {
+                let new_zoom = (zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+                // Keep the point under the pointer where it is
+                if let Some(p) = pointer {
+                    let from_center = p - area.center() - self.pan;
+                    self.pan -= from_center * (new_zoom / zoom - 1.0);
+                }
+                zoom = new_zoom;
+                self.zoom = Some(zoom);
+            }
+        }
+        if resp.dragged() {
+            self.pan += resp.drag_delta();
+            self.zoom = Some(zoom);
+        }
+
+        let rect = egui::Rect::from_center_size(area.center() + self.pan, img_size * zoom);
+        let uv = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+        ui.painter()
+            .with_clip_rect(area)
+            .image(tex.id(), rect, uv, egui::Color32::WHITE);
+        if resp.dragged() {
+            ui.ctx().set_cursor_icon(egui::CursorIcon::Grabbing);
+        } else if resp.hovered() {
+            ui.ctx().set_cursor_icon(egui::CursorIcon::Grab);
+        }
+    }
+}
+
+/// How often the entry was copied and restored, and when.
+fn usage_text(entry: &ClipboardEntry) -> String {
+    let u = &entry.usage;
+    let local = |t: DateTime<Utc>| t.with_timezone(&Local).format("%Y-%m-%d %H:%M");
+    let mut s = format!("Copied {}×, restored {}×", u.captures.max(1), u.restores);
+    if let Some(t) = u.first_seen {
+        s.push_str(&format!(", first seen {}", local(t)));
+    }
+    if let Some(t) = u.last_used {
+        s.push_str(&format!(", last used {}", local(t)));
+    }
+    s
+}
+
+fn text_ui(ui: &mut egui::Ui, text: &str) {
+    let lines: Vec<&str> = text.lines().collect();
+    ui.label(format!(
+        "{} characters, {} bytes, {} lines",
+        text.chars().count(),
+        text.len(),
+        lines.len()
+    ));
+    ui.separator();
+
+    let font = egui::TextStyle::Monospace.resolve(ui.style());
+    let row_height = ui.fonts(|f| f.row_height(&font));
+    let number_width = lines.len().max(1).to_string().len();
+    let weak = ui.visuals().weak_text_color();
+    let strong = ui.visuals().text_color();
+    // Only the lines in view are laid out, stack traces can be long
+    egui::ScrollArea::both().auto_shrink(false).show_rows(
+        ui,
+        row_height,
+        lines.len(),
+        |ui, range| {
+            for n in range {
+                let mut job = egui::text::LayoutJob::default();
+                let format = |color| egui::TextFormat::simple(font.clone(), color);
+                job.append(&format!("{:>number_width$}  ", n + 1), 0.0, format(weak));
+                job.append(lines[n], 0.0, format(strong));
+                ui.add(egui::Label::new(job).extend());
+            }
+        },
+    );
+}
@@ -0,0 +1,488 @@
+use anyhow::{Result, anyhow, bail};
+use chrono::{DateTime, Days, Local, NaiveDate, TimeDelta, TimeZone, Utc};
+use regex::{Regex, RegexBuilder};
+use std::ops::Range;
+use zeroize::Zeroize;
+
+use crate::fuzzy::{self, fuzzy_match};
+use crate::menu::url_in;
+use crate::types::{ClipboardContent, ClipboardEntry};
+
+/// What `type:` can ask for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Text,
+    Image,
+    Url,
+    Code,
+}
+
+#[derive(Debug)]
+enum Term {
+    /// Lowercased, matched case-insensitively anywhere in the text, or as
+    /// a subsequence when matching fuzzily
+    Word(String),
+    /// Lowercased, always matched as it is
+    Phrase(String),
+    Regex(Regex),
+    Type(Kind),
+    Before(DateTime<Utc>),
+    After(DateTime<Utc>),
+    Pinned(bool),
+}
+
+/// A parsed filter: every term has to match, or not match if negated.
+#[derive(Debug, Default)]
+pub struct Query {
+    terms: Vec<(bool, Term)>,
+}
+
+impl Query {
+    /// Whether `e` matches, and how well. With `fuzzy` the words match as
+    /// subsequences and the score adds up how well each did, otherwise every
+    /// match scores the same.
+    pub fn score(&self, e: &ClipboardEntry, fuzzy: bool) -> Option<i32> {
+        let mut score = 0;
+        for (negated, term) in &self.terms {
+            match (term, &e.content) {
+                (Term::Word(w), ClipboardContent::Text(t)) if fuzzy && !negated => {
+                    score += fuzzy_match(w, t)?.score;
+                }
+                _ if term_matches(term, e) == *negated => return None,
+                _ => {}
+            }
+        }
+        Some(score)
+    }
+
+    /// Sorted, non-overlapping byte ranges of `text` that the words,
+    /// phrases and regexes of the query match.
+    pub fn highlights(&self, text: &str, fuzzy: bool) -> Vec<Range<usize>> {
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        for (_, term) in self.terms.iter().filter(|(negated, _)| !negated) {
+            match term {
+                Term::Word(w) if fuzzy => {
+                    ranges.extend(fuzzy_match(w, text).into_iter().flat_map(|m| m.ranges));
+                }
+                Term::Word(q) | Term::Phrase(q) => ranges.extend(find_all(text, q)),
+                Term::Regex(re) => ranges.extend(re.find_iter(text).map(|m| m.range())),
+                _ => {}
+            }
+        }
+        ranges.sort_by_key(|r| r.start);
+        let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+        for r in ranges.into_iter().filter(|r| !r.is_empty()) {
+            match merged.last_mut() {
+                Some(last) if r.start <= last.end => last.end = last.end.max(r.end),
+                _ => merged.push(r),
+            }
+        }
+        merged
+    }
+}
+
+/// Every place `q` occurs in `text`, ignoring case.
+fn find_all(text: &str, q: &str) -> Vec<Range<usize>> {
+    let mut found = Vec::new();
+    if q.is_empty() {
+        return found;
+    }
+    let mut from = 0;
+    for (at, _) in text.char_indices() {
+        if at < from {
+            continue;
+        }
+        let mut rest = text[at..].chars();
+        let mut len = 0;
+        let hit = q.chars().all(|qc| {
+            rest.next().is_some_and(|c| {
+                len += c.len_utf8();
+                fuzzy::eq(c, qc)
+            })
+        });
+        if hit {
+            found.push(at..at + len);
+            from = at + len;
+        }
+    }
+    found
+}
+
+impl Drop for Query {
+    fn drop(&mut self) {
+        for (_, term) in &mut self.terms {
+            if let Term::Word(s) | Term::Phrase(s) = term {
+                s.zeroize();
+            }
+        }
+    }
+}
+
+fn term_matches(term: &Term, e: &ClipboardEntry) -> bool {
+    let text = match &e.content {
+        ClipboardContent::Text(t) => Some(t.as_str()),
+        ClipboardContent::ImageBase64(_) => None,
+    };
+    match term {
+        Term::Word(q) | Term::Phrase(q) => {
+            text.is_some_and(|t| t.to_lowercase().contains(q.as_str()))
+        }
+        Term::Regex(re) => text.is_some_and(|t| re.is_match(t)),
+        Term::Type(Kind::Image) => text.is_none(),
+        Term::Type(Kind::Text) => text.is_some(),
+        Term::Type(Kind::Url) => text.and_then(url_in).is_some(),
+        Term::Type(Kind::Code) => text.is_some_and(looks_like_code),
+        Term::Before(t) => e.ts < *t,
+        Term::After(t) => e.ts >= *t,
+        Term::Pinned(pinned) => e.pinned == *pinned,
+    }
+}
+
+/// Whether most non-empty lines of `text` read like source code or a shell
+/// command rather than prose.
+fn looks_like_code(text: &str) -> bool {
+    const TOKENS: [&str; 10] = ["=>", "->", "::", "==", "!=", "&&", "||", " | ", "();", "$("];
+    const STARTS: [&str; 12] = [
+        "$ ",
+        "fn ",
+        "def ",
+        "let ",
+        "const ",
+        "import ",
+        "#include",
+        "function ",
+        "class ",
+        "return ",
+        "pub ",
+        "SELECT ",
+    ];
+    let lines: Vec<&str> = text.lines().filter(|l| !l.trim().is_empty()).collect();
+    let codey = lines
+        .iter()
+        .filter(|l| {
+            let t = l.trim();
+            l.starts_with([' ', '\t']) && lines.len() > 1
+                || t.ends_with([';', '{', '}', '(', ')', '[', ']'])
+                || TOKENS.iter().any(|k| t.contains(k))
+                || STARTS.iter().any(|k| t.starts_with(k))
+        })
+        .count();
+    !lines.is_empty() && codey * 2 >= lines.len()
+}
+
+/// Parses a filter typed into the history window.
+pub fn parse(input: &str) -> Result<Query> {
+    parse_at(input, Local::now())
+}
+
+/// Like `parse`, with relative dates and durations taken from `now`.
+///
+/// Terms are separated by spaces and all have to match:
+/// - `word` or `"a phrase"`: the text contains it, ignoring case. Words
+///   match as a subsequence instead when scoring fuzzily.
+/// - `/regex/`: the text matches the regular expression, ignoring case
+/// - `type:text|image|url|code`
+/// - `after:DATE`, `before:DATE` with `today`, `yesterday` or `YYYY-MM-DD`
+/// - `within:30m`, with `s`, `m`, `h`, `d` or `w`
+/// - `is:pinned`, `pinned:yes|no`
+/// - `-` in front of any term negates it
+pub fn parse_at(input: &str, now: DateTime<Local>) -> Result<Query> {
+    let mut query = Query::default();
+    let mut rest = input.trim_start();
+    while !rest.is_empty() {
+        let negated = rest
+            .strip_prefix('-')
+            .is_some_and(|r| r.starts_with(|c: char| !c.is_whitespace()));
+        if negated {
+            rest = &rest[1..];
+        }
+        let (term, tail) = if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted
+                .find('"')
+                .ok_or_else(|| anyhow!("Missing closing \" after {rest}"))?;
+            (
+                Term::Phrase(quoted[..end].to_lowercase()),
+                &quoted[end + 1..],
+            )
+        } else if let Some((pattern, tail)) = regex_literal(rest) {
+            let re = RegexBuilder::new(&pattern.replace("\\/", "/"))
+                .case_insensitive(true)
+                .build()
+                .map_err(|e| anyhow!("Invalid regex /{pattern}/: {e}"))?;
+            (Term::Regex(re), tail)
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            (word_term(&rest[..end], now)?, &rest[end..])
+        };
+        query.terms.push((negated, term));
+        rest = tail.trim_start();
+    }
+    Ok(query)
+}
+
+/// Splits off `/pattern/` if `s` starts with one. The closing slash has to
+/// end a word, so paths like `/usr/bin` stay plain text.
+fn regex_literal(s: &str) -> Option<(&str, &str)> {
+    let body = s.strip_prefix('/')?;
+    let mut escaped = false;
+    for (i, c) in body.char_indices() {
+        match c {
+            '\\' => escaped = !escaped,
+            '/' if !escaped => {
+                let tail = &body[i + 1..];
+                if i > 0 && tail.chars().next().is_none_or(char::is_whitespace) {
+                    return Some((&body[..i], tail));
+                }
+            }
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+fn word_term(word: &str, now: DateTime<Local>) -> Result<Term> {
+    let Some((key, value)) = word.split_once(':') else {
+        return Ok(Term::Word(word.to_lowercase()));
+    };
+    let value_lc = value.to_lowercase();
+    Ok(match key.to_lowercase().as_str() {
+        "type" => Term::Type(match value_lc.as_str() {
+            "text" => Kind::Text,
+            "image" | "img" => Kind::Image,
+            "url" | "link" => Kind::Url,
+            "code" => Kind::Code,
+            _ => bail!("Unknown type \"{value}\", use text, image, url or code"),
+        }),
+        "after" => Term::After(day_start(value, now)?),
+        "before" => Term::Before(day_start(value, now)?),
+        "within" => Term::After(since(value, now)?),
+        "is" if value_lc == "pinned" => Term::Pinned(true),
+        "is" => bail!("Unknown is:{value}, only is:pinned is known"),
+        "pinned" => Term::Pinned(match value_lc.as_str() {
+            "yes" | "true" => true,
+            "no" | "false" => false,
+            _ => bail!("Use pinned:yes or pinned:no"),
+        }),
+        // Anything else, like the scheme of a URL, is plain text
+        _ => Term::Word(word.to_lowercase()),
+    })
+}
+
+/// Start of the local day `value` names.
+fn day_start(value: &str, now: DateTime<Local>) -> Result<DateTime<Utc>> {
+    let today = now.date_naive();
+    let date = match value.to_lowercase().as_str() {
+        "today" => today,
+        "yesterday" => today - Days::new(1),
+        _ => NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .map_err(|_| anyhow!("Unknown date \"{value}\", use today, yesterday or YYYY-MM-DD"))?,
+    };
+    let midnight = date.and_hms_opt(0, 0, 0).expect("midnight");
+    // A midnight skipped by a DST change is taken as UTC
+    let start = Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .unwrap_or_else(|| Local.from_utc_datetime(&midnight));
+    Ok(start.with_timezone(&Utc))
+}
+
+/// The moment the duration `value` lasts back from `now`. Durations too
+/// long to represent are unknown like any other.
+fn since(value: &str, now: DateTime<Local>) -> Result<DateTime<Utc>> {
+    let err = || anyhow!("Unknown duration \"{value}\", use e.g. 30m, 2h or 3d");
+    let split = value.find(|c: char| !c.is_ascii_digit()).ok_or_else(err)?;
+    let n: i64 = value[..split].parse().map_err(|_| err())?;
+    let duration = match &value[split..] {
+        "s" => TimeDelta::try_seconds(n),
+        "m" | "min" => TimeDelta::try_minutes(n),
+        "h" => TimeDelta::try_hours(n),
+        "d" => TimeDelta::try_days(n),
+        "w" => TimeDelta::try_weeks(n),
+        _ => None,
+    };
+    duration
+        .and_then(|d| now.with_timezone(&Utc).checked_sub_signed(d))
+        .ok_or_else(err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Selection;
+
+    fn now() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2026, 10, 18, 9, 30, 0).unwrap()
+    }
+
+    fn text(t: &str, hours_ago: i64) -> ClipboardEntry {
+        entry(ClipboardContent::Text(t.to_owned()), hours_ago)
+    }
+
+    fn entry(content: ClipboardContent, hours_ago: i64) -> ClipboardEntry {
+        ClipboardEntry {
+            ts: now().with_timezone(&Utc) - chrono::Duration::hours(hours_ago),
+            content,
+            pinned: false,
+            source: Selection::Clipboard,
+            targets: Vec::new(),
+            thumb: None,
+            secrets: Vec::new(),
+            expires: None,
+            usage: Default::default(),
+        }
+    }
+
+    fn matches(query: &str, e: &ClipboardEntry) -> bool {
+        parse_at(query, now()).unwrap().score(e, false).is_some()
+    }
+
+    fn fuzzy(query: &str, t: &str) -> Option<i32> {
+        parse_at(query, now()).unwrap().score(&text(t, 0), true)
+    }
+
+    #[test]
+    fn words_and_phrases() {
+        let e = text("Hello World, hello again", 0);
+        assert!(matches("", &e));
+        assert!(matches("hello", &e));
+        assert!(matches("WORLD again", &e));
+        assert!(!matches("world missing", &e));
+        assert!(matches("\"world, hello\"", &e));
+        assert!(!matches("\"hello hello\"", &e));
+        assert!(matches("  hello   world  ", &e));
+    }
+
+    #[test]
+    fn negation() {
+        let e = text("cargo build --release", 0);
+        assert!(matches("cargo -test", &e));
+        assert!(!matches("-cargo", &e));
+        assert!(!matches("-\"build --release\"", &e));
+        assert!(matches("-type:image", &e));
+        // A lone dash is just text
+        assert!(matches("-", &text("a - b", 0)));
+    }
+
+    #[test]
+    fn regex() {
+        let e = text("Order #12345 shipped", 0);
+        assert!(matches("/#\\d{5}/", &e));
+        assert!(matches("/ORDER #1/", &e));
+        assert!(!matches("/^shipped/", &e));
+        assert!(matches("/order .*shipped/ -/^x/", &e));
+        assert!(matches("/a\\/b/", &text("a/b", 0)));
+        assert!(parse_at("/(unclosed/", now()).is_err());
+    }
+
+    #[test]
+    fn paths_are_not_regexes() {
+        let e = text("/usr/local/bin/cargo", 0);
+        assert!(matches("/usr/local/bin", &e));
+        assert!(!matches("/usr/local/bin", &text("usr local bin", 0)));
+    }
+
+    #[test]
+    fn types() {
+        let img = entry(ClipboardContent::ImageBase64(String::new()), 0);
+        let url = text("https://example.com/page?q=1", 0);
+        let code = text("fn main() {\n    println!(\"hi\");\n}", 0);
+        let prose = text("Meet at noon, bring the slides.", 0);
+
+        assert!(matches("type:image", &img));
+        assert!(!matches("type:text", &img));
+        assert!(!matches("hello", &img));
+        assert!(matches("type:url", &url));
+        assert!(!matches("type:url", &prose));
+        assert!(matches("type:code", &code));
+        assert!(matches("type:code", &text("ls -la | grep cargo", 0)));
+        assert!(!matches("type:code", &prose));
+        assert!(matches("type:text", &prose));
+        assert!(parse_at("type:video", now()).is_err());
+    }
+
+    #[test]
+    fn dates() {
+        // now() is 09:30, so 12 hours ago was yesterday evening
+        let this_morning = text("a", 2);
+        let last_night = text("b", 12);
+        let last_week = text("c", 24 * 7);
+
+        assert!(matches("after:today", &this_morning));
+        assert!(!matches("after:today", &last_night));
+        assert!(matches("after:yesterday", &last_night));
+        assert!(!matches("after:yesterday", &last_week));
+        assert!(matches("before:today", &last_night));
+        assert!(matches("before:2026-10-12", &last_week));
+        assert!(!matches("before:2026-10-11", &last_week));
+        assert!(matches("after:2026-10-11 before:2026-10-12", &last_week));
+        assert!(parse_at("after:tomorrowish", now()).is_err());
+        assert!(parse_at("before:2026-13-01", now()).is_err());
+    }
+
+    #[test]
+    fn within() {
+        let e = text("a", 3);
+        assert!(matches("within:4h", &e));
+        assert!(!matches("within:2h", &e));
+        assert!(matches("within:1d", &e));
+        assert!(!matches("within:90m", &e));
+        assert!(matches("within:1w", &e));
+        assert!(parse_at("within:2", now()).is_err());
+        assert!(parse_at("within:h", now()).is_err());
+        assert!(parse_at("within:2y", now()).is_err());
+        // Too far back for a date, or for a duration at all
+        assert!(parse_at("within:99999999d", now()).is_err());
+        assert!(parse_at("within:999999999999d", now()).is_err());
+        assert!(parse_at("within:99999999999999999999s", now()).is_err());
+    }
+
+    #[test]
+    fn pinned() {
+        let mut e = text("a", 0);
+        assert!(!matches("is:pinned", &e));
+        assert!(matches("-is:pinned", &e));
+        assert!(matches("pinned:no", &e));
+        e.pinned = true;
+        assert!(matches("is:pinned", &e));
+        assert!(matches("pinned:yes", &e));
+        assert!(parse_at("is:secret", now()).is_err());
+        assert!(parse_at("pinned:maybe", now()).is_err());
+    }
+
+    #[test]
+    fn unknown_keys_are_text() {
+        let e = text("see http://example.com", 0);
+        assert!(matches("http://example.com", &e));
+        assert!(matches("see:", &text("see: below", 0)));
+    }
+
+    #[test]
+    fn fuzzy_words() {
+        assert!(fuzzy("crgo bld", "cargo build --release").is_some());
+        assert!(fuzzy("bld crgo", "cargo build --release").is_some());
+        assert!(fuzzy("cargo zz", "cargo build --release").is_none());
+        // Phrases and negated words still match exactly
+        assert!(fuzzy("\"crgo\"", "cargo build").is_none());
+        assert!(fuzzy("-crgo", "cargo build").is_some());
+        assert!(fuzzy("-cargo", "cargo build").is_none());
+        // Tight and word-start matches rank higher
+        assert!(fuzzy("build", "cargo build") > fuzzy("build", "b u i l d"));
+        assert!(fuzzy("cb", "cargo build") > fuzzy("cb", "xcxb"));
+    }
+
+    #[test]
+    fn highlights() {
+        let q = parse_at("hello /w.rld/ \"again\"", now()).unwrap();
+        let t = "Hello World, hello again";
+        assert_eq!(q.highlights(t, false), vec![0..5, 6..11, 13..18, 19..24]);
+        let q = parse_at("hlo -world", now()).unwrap();
+        assert_eq!(q.highlights("Hello", true), vec![0..1, 2..3, 4..5]);
+        assert!(q.highlights("Hello", false).is_empty());
+    }
+
+    #[test]
+    fn invalid_queries() {
+        assert!(parse_at("\"unterminated", now()).is_err());
+        assert!(parse_at("/(/", now()).is_err());
+    }
+}
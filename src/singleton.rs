@@ -3,16 +3,22 @@ use std::net::{TcpListener, TcpStream};
 
 const ACTIVATE_ADDR: &str = "127.0.0.1:57577";
 
-pub fn setup_single_instance(activate_tx: crossbeam::channel::Sender<()>) -> bool {
+use crate::types::InstanceMsg;
+
+pub fn setup_single_instance(activate_tx: crossbeam::channel::Sender<InstanceMsg>) -> bool {
     match TcpListener::bind(ACTIVATE_ADDR) {
         Ok(listener) => {
             std::thread::spawn(move || {
-                for stream in listener.incoming() {
-                    if let Ok(mut s) = stream {
-                        let mut _buf = [0u8; 4];
-                        let _ = s.read(&mut _buf);
-                        let _ = activate_tx.send(());
-                    }
+                for mut s in listener.incoming().flatten() {
+                    let mut buf = [0u8; 4];
+                    let _ = s.read(&mut buf);
+                    let msg = match &buf {
+                        b"PING" => continue,
+                        b"LOCK" => InstanceMsg::Lock,
+                        b"TOGG" => InstanceMsg::Toggle,
+                        _ => InstanceMsg::Show,
+                    };
+                    let _ = activate_tx.send(msg);
                 }
             });
             true
@@ -25,3 +31,19 @@ pub fn setup_single_instance(activate_tx: crossbeam::channel::Sender<()>) -> boo
         }
     }
 }
+
+/// Sends `msg` to the running instance, returns `false` if there is none.
+pub fn send_to_instance(msg: &[u8; 4]) -> bool {
+    match TcpStream::connect(ACTIVATE_ADDR) {
+        Ok(mut s) => {
+            let _ = s.write_all(msg);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Whether another ClipVault process currently holds the single-instance port.
+pub fn instance_running() -> bool {
+    send_to_instance(b"PING")
+}
@@ -0,0 +1,161 @@
+use crossbeam::channel::{Receiver, Sender, unbounded};
+use std::collections::{HashMap, HashSet};
+use std::thread;
+use zeroize::Zeroize;
+
+use crate::img::{base64_to_imagedata, thumbnail};
+
+/// Thumbnails the history list keeps on the GPU, in bytes.
+const TEXTURE_BUDGET: usize = 64 * 1024 * 1024;
+
+/// Creates thumbnails for images recorded before thumbnails existed, on a
+/// background thread so decoding a large screenshot doesn't stall the UI.
+pub struct Thumbnailer {
+    tx: Sender<(String, String)>,
+    rx: Receiver<(String, Option<String>)>,
+    // Requested and not done yet, or failed and not worth retrying
+    queued: HashSet<String>,
+}
+
+impl Thumbnailer {
+    pub fn spawn() -> Self {
+        let (tx, jobs) = unbounded::<(String, String)>();
+        let (done, rx) = unbounded();
+        thread::spawn(move || {
+            for (key, mut b64) in jobs {
+                let thumb = thumbnail(&b64)
+                    .inspect_err(|e| eprintln!("Failed to create a thumbnail: {e}"))
+                    .ok();
+                b64.zeroize();
+                if done.send((key, thumb)).is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            tx,
+            rx,
+            queued: HashSet::new(),
+        }
+    }
+
+    /// Queues a thumbnail of the image `b64` for the entry `key`.
+    pub fn request(&mut self, key: &str, b64: &str) {
+        if self.queued.insert(key.to_owned()) {
+            let _ = self.tx.send((key.to_owned(), b64.to_owned()));
+        }
+    }
+
+    /// Thumbnails finished since the last call, by entry key.
+    pub fn finished(&mut self) -> Vec<(String, String)> {
+        self.rx
+            .try_iter()
+            .filter_map(|(key, thumb)| {
+                let thumb = thumb?;
+                self.queued.remove(&key);
+                Some((key, thumb))
+            })
+            .collect()
+    }
+}
+
+struct CachedTexture {
+    handle: egui::TextureHandle,
+    bytes: usize,
+    used: u64,
+}
+
+/// Thumbnail textures by entry key. Once they take more than
+/// `TEXTURE_BUDGET` bytes the least recently shown ones are dropped, and
+/// loaded again when they scroll back into view.
+#[derive(Default)]
+pub struct TextureCache {
+    textures: HashMap<String, CachedTexture>,
+    bytes: usize,
+    // Counts frames, see `begin_frame`
+    clock: u64,
+    failed: HashSet<String>,
+}
+
+impl TextureCache {
+    pub fn begin_frame(&mut self) {
+        self.clock += 1;
+    }
+
+    /// Texture of the base64 PNG `b64`, uploaded on first use.
+    pub fn get(
+        &mut self,
+        ctx: &egui::Context,
+        key: &str,
+        b64: &str,
+    ) -> Option<egui::TextureHandle> {
+        if let Some(t) = self.textures.get_mut(key) {
+            t.used = self.clock;
+            return Some(t.handle.clone());
+        }
+        if self.failed.contains(key) {
+            return None;
+        }
+
+        let Ok(img) = base64_to_imagedata(b64) else {
+            self.failed.insert(key.to_owned());
+            return None;
+        };
+        let color: egui::ColorImage =
+            egui::ColorImage::from_rgba_unmultiplied([img.width, img.height], &img.bytes);
+        let handle: egui::TextureHandle = ctx.load_texture(
+            format!("thumb-{key}"),
+            color,
+            egui::TextureOptions::LINEAR, // smooth when scaled down
+        );
+        let bytes = img.width * img.height * 4;
+        self.bytes += bytes;
+        self.textures.insert(
+            key.to_owned(),
+            CachedTexture {
+                handle: handle.clone(),
+                bytes,
+                used: self.clock,
+            },
+        );
+        self.evict();
+        Some(handle)
+    }
+
+    /// Drops least recently used textures until the cache fits its budget,
+    /// never one shown in the current frame.
+    fn evict(&mut self) {
+        while self.bytes > TEXTURE_BUDGET {
+            let Some(key) = self
+                .textures
+                .iter()
+                .filter(|(_, t)| t.used < self.clock)
+                .min_by_key(|(_, t)| t.used)
+                .map(|(k, _)| k.clone())
+            else {
+                break;
+            };
+            self.remove(&key);
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        if let Some(t) = self.textures.remove(key) {
+            self.bytes -= t.bytes;
+        }
+        self.failed.remove(key);
+    }
+
+    pub fn retain(&mut self, mut keep: impl FnMut(&str) -> bool) {
+        let gone: Vec<String> = self.textures.keys().filter(|k| !keep(k)).cloned().collect();
+        for key in gone {
+            self.remove(&key);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.textures.clear();
+        self.failed.clear();
+        self.bytes = 0;
+    }
+}
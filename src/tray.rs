@@ -10,13 +10,18 @@ use tray_icon::{MouseButton, TrayIcon, TrayIconEvent};
 
 pub struct Tray {
     #[cfg(not(target_os = "linux"))]
-    _icon: TrayIcon,
+    icon: TrayIcon,
     pub open_id: MenuId,
+    pub lock_id: MenuId,
     pub quit_id: MenuId,
+    // The icon lives on the GTK thread, which picks new tooltips up from here
+    #[cfg(target_os = "linux")]
+    tooltip_tx: std::sync::mpsc::Sender<String>,
 }
 
 pub enum TrayEvent {
     OpenRequested,
+    LockRequested,
     QuitRequested,
     None,
 }
@@ -25,15 +30,18 @@ impl Tray {
         #[cfg(target_os = "linux")]
         {
             use std::sync::mpsc;
-            let (tx_ids, rx_ids) = mpsc::sync_channel::<(MenuId, MenuId)>(1);
+            let (tx_ids, rx_ids) = mpsc::sync_channel::<(MenuId, MenuId, MenuId)>(1);
+            let (tooltip_tx, tooltip_rx) = mpsc::channel::<String>();
 
             std::thread::spawn(move || {
                 gtk::init().expect("gtk::init failed");
 
                 let menu = Menu::new();
                 let open = MenuItem::new("Open", true, None);
+                let lock = MenuItem::new("Lock", true, None);
                 let quit = MenuItem::new("Quit", true, None);
                 menu.append(&open).unwrap();
+                menu.append(&lock).unwrap();
                 menu.append(&quit).unwrap();
 
                 let icon = crate::assets::get_bytes(ICON_TRAY)
@@ -41,7 +49,7 @@ impl Tray {
                     .unwrap();
                 let icon = crate::assets::tray_icon_from_png(&icon).unwrap();
 
-                let _tray_icon = TrayIconBuilder::new()
+                let tray_icon = TrayIconBuilder::new()
                     .with_tooltip("ClipVault")
                     .with_menu(Box::new(menu))
                     .with_icon(icon)
@@ -50,22 +58,40 @@ impl Tray {
 
                 // Send IDs back so main thread can match MenuEvent ids.
                 tx_ids
-                    .send((open.id().to_owned(), quit.id().to_owned()))
+                    .send((
+                        open.id().to_owned(),
+                        lock.id().to_owned(),
+                        quit.id().to_owned(),
+                    ))
                     .ok();
 
+                gtk::glib::timeout_add_local(std::time::Duration::from_millis(250), move || {
+                    if let Some(tooltip) = tooltip_rx.try_iter().last() {
+                        let _ = tray_icon.set_tooltip(Some(tooltip));
+                    }
+                    gtk::glib::ControlFlow::Continue
+                });
+
                 gtk::main();
             });
 
-            let (open_id, quit_id) = rx_ids.recv()?;
-            Ok(Self { open_id, quit_id })
+            let (open_id, lock_id, quit_id) = rx_ids.recv()?;
+            Ok(Self {
+                open_id,
+                lock_id,
+                quit_id,
+                tooltip_tx,
+            })
         }
 
         #[cfg(not(target_os = "linux"))]
         {
             let menu = Menu::new();
             let open = MenuItem::new("Open", true, None);
+            let lock = MenuItem::new("Lock", true, None);
             let quit = MenuItem::new("Quit", true, None);
             menu.append(&open)?;
+            menu.append(&lock)?;
             menu.append(&quit)?;
 
             let icon = crate::assets::get_bytes(ICON_TRAY)
@@ -81,12 +107,21 @@ impl Tray {
 
             Ok(Self {
                 open_id: open.id().to_owned(),
+                lock_id: lock.id().to_owned(),
                 quit_id: quit.id().to_owned(),
-                _icon: tray_icon,
+                icon: tray_icon,
             })
         }
     }
 
+    pub fn set_tooltip(&self, tooltip: &str) {
+        #[cfg(target_os = "linux")]
+        let _ = self.tooltip_tx.send(tooltip.to_string());
+
+        #[cfg(not(target_os = "linux"))]
+        let _ = self.icon.set_tooltip(Some(tooltip));
+    }
+
     pub fn try_recv(&self) -> TrayEvent {
         #[cfg(not(target_os = "linux"))]
         if let Ok(ev) = TrayIconEvent::receiver().try_recv() {
@@ -103,6 +138,8 @@ impl Tray {
         if let Ok(ev) = TrayMenuEvent::receiver().try_recv() {
             if ev.id == self.open_id {
                 return TrayEvent::OpenRequested;
+            } else if ev.id == self.lock_id {
+                return TrayEvent::LockRequested;
             } else if ev.id == self.quit_id {
                 return TrayEvent::QuitRequested;
             }
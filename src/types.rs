@@ -1,16 +1,114 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::secrets::Detector;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ClipboardContent {
     Text(String),
     ImageBase64(String),
 }
 
+/// The X11/Wayland selection an entry was copied from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Selection {
+    /// Explicit copy and paste
+    #[default]
+    Clipboard,
+    /// Selected with the mouse, pasted with middle-click
+    Primary,
+}
+
+impl Selection {
+    pub const ALL: [Selection; 2] = [Selection::Clipboard, Selection::Primary];
+
+    pub fn other(self) -> Self {
+        match self {
+            Selection::Clipboard => Selection::Primary,
+            Selection::Primary => Selection::Clipboard,
+        }
+    }
+}
+
+/// Another representation offered alongside an entry's text or image, e.g.
+/// the HTML of a browser selection or the URI list of copied files.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Target {
+    pub mime: String,
+    /// Raw bytes, base64 encoded
+    pub data: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClipboardEntry {
     pub ts: DateTime<Utc>,
     pub content: ClipboardContent,
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub source: Selection,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<Target>,
+    /// Downscaled copy of an image entry, see `img::thumbnail`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumb: Option<String>,
+    /// Credentials found in the text, see `secrets::scan`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub secrets: Vec<Detector>,
+    /// When the entry is dropped from the history on its own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub usage: Usage,
+}
+
+/// What a copy of already recorded content says about it, replacing what the
+/// earlier copy did: another selection, other formats, or secrets found by a
+/// detector enabled since.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recopy {
+    #[serde(default)]
+    pub source: Selection,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<Target>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub secrets: Vec<Detector>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<DateTime<Utc>>,
+}
+
+/// How often and when an entry was copied and restored.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Usage {
+    /// Times the content was copied, `0` for entries from before this was counted
+    pub captures: u32,
+    /// Times it was restored from the history
+    pub restores: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_seen: Option<DateTime<Utc>>,
+    /// Last time it was restored
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_used: Option<DateTime<Utc>>,
+}
+
+impl ClipboardEntry {
+    /// Frecency of the entry at `now`: how often it was used, weighted by
+    /// how recently, the way browsers rank their history. Restores count
+    /// more than copies.
+    pub fn frecency(&self, now: DateTime<Utc>) -> u32 {
+        let last = self.usage.last_used.map_or(self.ts, |t| t.max(self.ts));
+        let weight = match (now - last).num_days() {
+            ..=4 => 100,
+            5..=14 => 70,
+            15..=31 => 50,
+            32..=90 => 30,
+            _ => 10,
+        };
+        let uses = self.usage.captures.max(1) + 2 * self.usage.restores;
+        uses.saturating_mul(weight)
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -18,24 +116,44 @@ pub struct ClipboardEntry {
 pub enum LogRec {
     Put {
         key: String,
-        ts: DateTime<Utc>,
-        content: ClipboardContent,
+        #[serde(flatten)]
+        entry: ClipboardEntry,
     },
+    /// The content was copied again
     Touch {
         key: String,
         ts: DateTime<Utc>,
+        /// Missing from journals written before copies were reclassified,
+        /// which leave the entry as it was
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        recopy: Option<Recopy>,
+    },
+    /// The entry was restored from the history
+    Use {
+        key: String,
+        ts: DateTime<Utc>,
+    },
+    Remove {
+        key: String,
+    },
+    Pin {
+        key: String,
+        pinned: bool,
     },
 }
 
 #[derive(Debug, Clone)]
 pub enum HotkeyMsg {
     ToggleWindow,
+    Lock,
 }
 
-#[derive(Debug)]
-pub enum UnlockResult {
-    Unlocked { key: [u8; 32], nonce: [u8; 24] },
-    Cancelled,
+/// Messages sent by a second `clipvault` process to the running one.
+#[derive(Debug, Clone, Copy)]
+pub enum InstanceMsg {
+    Show,
+    Toggle,
+    Lock,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -1,91 +1,87 @@
 use crate::assets::{ICON_IMAGE_FILTER, ICON_SETTINGS, load_texture_from_asset};
-use crate::clip::{content_key, set_clipboard};
-use crate::crypto::{decrypt_file, derivate_crypto_params, derive_save_nonce};
-use crate::img::base64_to_imagedata;
+use crate::clip::{
+    clipboard_entry_hash, content_key, focused_window, paste_into, read_selection,
+    set_clipboard, spawn_watcher,
+};
+use crate::crypto::{KdfParams, derivate_crypto_params};
 use crate::paths::history_path;
-use crate::storage::Store;
+use crate::storage::{NewPassphrase, Store, vault_kdf};
+use crate::thumbs::{TextureCache, Thumbnailer};
 use crate::tray;
 use crate::tray::TrayEvent;
-use crate::types::{ClipboardContent, ClipboardEntry, HotkeyMsg, Meta, UnlockResult};
+use crate::types::{
+    ClipboardContent, ClipboardEntry, HotkeyMsg, InstanceMsg, Selection, Target, Usage,
+};
+use crate::editor::{Editor, EditorAction};
+use crate::img;
+use crate::menu::{self, RowAction};
 use crate::prefs;
+use crate::preview::Preview;
+use crate::query::{self, Query};
+use crate::secrets::{self, Detector, SecretAction};
 
-use anyhow::anyhow;
 use chrono::Utc;
-use crossbeam::channel::Receiver;
+use crossbeam::channel::{Receiver, TryRecvError, bounded};
 use egui::{RichText, StrokeKind, text::{CCursor, CCursorRange}};
 use notify_rust::{Notification, Timeout, Urgency};
-use std::{collections::HashMap, thread, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+    sync::{Arc, RwLock},
+    thread,
+    time::{Duration, Instant},
+};
+use zeroize::Zeroize;
 
-pub struct ClipAppLocked {
+// Height of a history row that hasn't been laid out yet
+const ROW_HEIGHT_GUESS: f32 = 32.0;
+// How long the pointer rests on a row before it opens in the preview
+const PREVIEW_HOVER_DELAY: Duration = Duration::from_millis(1000);
+
+/// Passphrase prompt shown while the vault is locked.
+struct LockScreen {
     passphrase: String,
-    key: [u8; 32],
-    nonce: [u8; 24],
-    loaded_crypto_params: bool,
     create_mode: bool,
     focus_password_done: bool,
+}
 
-    outcome_tx: Option<crossbeam::channel::Sender<UnlockResult>>,
-    outcome_sent: bool,
+/// Key material handed from the lock screen to `Store::open_or_create`.
+struct Unlocked {
+    key: [u8; 32],
+    nonce: [u8; 24],
+    kdf: KdfParams,
 }
 
-impl ClipAppLocked {
-    pub fn new(outcome_tx: crossbeam::channel::Sender<UnlockResult>) -> Self {
+impl Drop for Unlocked {
+    fn drop(&mut self) {
+        self.key.zeroize();
+        self.nonce.zeroize();
+    }
+}
+
+impl LockScreen {
+    fn new() -> Self {
         Self {
             passphrase: String::new(),
-            key: [0; 32],
-            nonce: [0; 24],
-            loaded_crypto_params: false,
             create_mode: !history_path().exists(),
             focus_password_done: false,
-            outcome_tx: Some(outcome_tx),
-            outcome_sent: false,
         }
     }
 
-    pub fn set_crypto_params(&mut self) {
-        let (key, nonce) = derivate_crypto_params(self.passphrase.clone());
-        self.key = key;
-        self.nonce = nonce;
-        self.loaded_crypto_params = true;
+    fn reset(&mut self) {
+        self.passphrase.zeroize();
+        self.create_mode = !history_path().exists();
+        self.focus_password_done = false;
     }
 
-    pub fn try_decrypt_history(&self) -> anyhow::Result<()> {
-        let path = history_path();
-        let meta_path = path.with_extension("meta.json");
-
-        // Helper to attempt a decrypt with a given nonce
-        let try_nonce = |nonce: [u8; 24]| -> anyhow::Result<()> {
-            decrypt_file(path.to_str().unwrap(), &self.key, &nonce).0
-        };
-
-        if meta_path.exists() {
-            // Read sidecar meta to learn the save counter
-            let bytes = std::fs::read(&meta_path)?;
-            let meta: Meta = serde_json::from_slice(&bytes)?;
-            if meta.next_counter > 0 {
-                // Most recent successful save used counter = next_counter - 1
-                let c_prev = meta.next_counter.saturating_sub(1);
-                let n_prev = derive_save_nonce(&self.key, &self.nonce, c_prev);
-                if try_nonce(n_prev).is_ok() {
-                    return Ok(());
-                }
-                // If meta advanced before data finished writing, try current next_counter
-                let n_curr = derive_save_nonce(&self.key, &self.nonce, meta.next_counter);
-                if try_nonce(n_curr).is_ok() {
-                    return Ok(());
-                }
-            }
-            // Fallback for very old files that used the base nonce directly
-            try_nonce(self.nonce)
-                .map_err(|_| anyhow!("Decryption failed with derived and base nonces"))
+    fn derive_crypto_params(&self) -> anyhow::Result<Unlocked> {
+        let kdf = if self.create_mode {
+            KdfParams::generate()?
         } else {
-            // If no legacy file; try base nonce, then first derived counter = 1
-            if try_nonce(self.nonce).is_ok() {
-                return Ok(());
-            }
-            let n1 = derive_save_nonce(&self.key, &self.nonce, 1);
-            try_nonce(n1).map_err(|_| anyhow!("Decryption failed (no meta present)"))
-        }
+            vault_kdf()?
+        };
+        let (key, nonce) = derivate_crypto_params(self.passphrase.clone(), &kdf)?;
+        Ok(Unlocked { key, nonce, kdf })
     }
 
     fn passphrase_ui(&mut self, ui: &mut egui::Ui) -> bool {
@@ -144,58 +140,29 @@ impl ClipAppLocked {
         submit
     }
 
-    fn handle_submit(&mut self, ctx: &egui::Context) {
+    fn handle_submit(&mut self) -> Option<Unlocked> {
         if self.passphrase.is_empty() {
-            self.notify_error("Passphrase cannot be empty.");
-            return;
+            notify("Passphrase cannot be empty.");
+            return None;
         }
-        self.set_crypto_params();
-        if self.create_mode {
-            if let Some(tx) = self.outcome_tx.take() {
-                let _ = tx.send(UnlockResult::Unlocked {
-                    key: self.key,
-                    nonce: self.nonce,
-                });
-                self.outcome_sent = true;
-            }
-            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
-        } else {
-            if self.try_decrypt_history().is_ok() {
-                if let Some(tx) = self.outcome_tx.take() {
-                    let _ = tx.send(UnlockResult::Unlocked {
-                        key: self.key,
-                        nonce: self.nonce,
-                    });
-                    self.outcome_sent = true;
-                }
-                ctx.send_viewport_cmd(egui::ViewportCommand::Close);
-            } else {
-                self.notify_error("Wrong passphrase. Please try again.");
+        let unlocked = match self.derive_crypto_params() {
+            Ok(u) => u,
+            Err(e) => {
+                eprintln!("Key derivation failed: {e}");
+                notify("Could not read the vault header.");
+                return None;
             }
+        };
+        if !self.create_mode && Store::verify(&unlocked.key, &unlocked.nonce).is_err() {
+            notify("Wrong passphrase. Please try again.");
+            return None;
         }
+        self.passphrase.zeroize();
+        Some(unlocked)
     }
 
-    fn notify_error(&self, msg: &str) {
-        match Notification::new()
-            .summary("ClipVault")
-            .body(msg)
-            .urgency(Urgency::Normal)
-            .timeout(Timeout::Milliseconds(4000))
-            .show()
-        {
-            Ok(handle) => {
-                thread::spawn(move || {
-                    thread::sleep(Duration::from_millis(4500));
-                    drop(handle);
-                });
-            }
-            Err(e) => eprintln!("Notification failed: {e}"),
-        }
-    }
-}
-
-impl eframe::App for ClipAppLocked {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+    fn show(&mut self, ctx: &egui::Context) -> Option<Unlocked> {
+        let mut unlocked = None;
         egui::CentralPanel::default().show(ctx, |ui| {
             let msg_locked = "ClipVault is locked.\n\nTo unlock you need to enter the passphrase.";
             let msg_create = "ClipVault is not initialized.\n\nSet passphrase first.";
@@ -210,63 +177,469 @@ impl eframe::App for ClipAppLocked {
             ui.separator();
 
             if self.passphrase_ui(ui) {
-                self.handle_submit(ctx);
+                unlocked = self.handle_submit();
             }
         });
+        unlocked
     }
+}
 
-    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
-        if !self.outcome_sent {
-            if let Some(tx) = self.outcome_tx.take() {
-                let _ = tx.send(UnlockResult::Cancelled);
-            }
+fn notify(msg: &str) {
+    match Notification::new()
+        .summary("ClipVault")
+        .body(msg)
+        .urgency(Urgency::Normal)
+        .timeout(Timeout::Milliseconds(4000))
+        .show()
+    {
+        Ok(handle) => {
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(4500));
+                drop(handle);
+            });
         }
-
-        self.key = [0; 32];
-        self.nonce = [0; 24];
-        self.passphrase.clear();
+        Err(e) => eprintln!("Notification failed: {e}"),
     }
 }
 
+const LOCKED_SIZE: [f32; 2] = [300.0, 118.0];
+const UNLOCKED_SIZE: [f32; 2] = [512.0, 600.0];
+
 pub struct ClipApp {
     tray: std::sync::Arc<tray::Tray>,
-    clipboard_rx: crossbeam::channel::Receiver<ClipboardEntry>,
-    store: Store,
+    clipboard_rx: Option<Receiver<ClipboardEntry>>,
+    // `None` while locked; dropping the store zeroizes its key and entries
+    store: Option<Store>,
+    lock_screen: LockScreen,
+    ever_unlocked: bool,
+    lock_requested: bool,
+    quitting: bool,
+    last_activity: Instant,
     filter: String,
-    tex_cache: HashMap<String, egui::TextureHandle>,
-    activate_rx: crossbeam::channel::Receiver<()>,
+    textures: TextureCache,
+    thumbnailer: Thumbnailer,
+    instance_rx: Receiver<InstanceMsg>,
     hotkey_rx: Receiver<HotkeyMsg>,
     window_visible: bool,
     show_settings: bool,
     show_timestamps: bool,
-    auto_launch: bool,
+    // `None` shows entries from both selections
+    source_filter: Option<Selection>,
+    prefs: prefs::Prefs,
+    // Shared with the clipboard watcher thread
+    capture: Arc<RwLock<prefs::Capture>>,
+    new_ignored_app: String,
+    // Keys of masked entries currently shown in clear
+    revealed: HashSet<String>,
+    pending_clear: Option<PendingClear>,
+    // Window focused before ClipVault showed up, pasted into on select
+    paste_target: Option<u32>,
+    list: ListCache,
+    // Measured height of each history row, by entry key
+    row_heights: HashMap<String, f32>,
+    // Key of the row picked with the keyboard
+    selected: Option<String>,
+    // Arrow keys and Tab taken out of egui's input, see `raw_input_hook`
+    nav_step: isize,
+    nav_tab: bool,
+    scroll_to_selected: bool,
+    preview: Option<Preview>,
+    editor: Option<Editor>,
+    // Row under the pointer, since when, and whether it was previewed already
+    hover: Option<(String, Instant, bool)>,
+    // Seconds last shown in the tray tooltip
+    tooltip_secs: Option<u64>,
+    passphrase_form: PassphraseForm,
+    retention_form: RetentionForm,
+}
+
+/// The history rows passing the filters. Lowercasing every text for the
+/// filter is too slow to redo each frame, so the rows are only rebuilt when
+/// the filters or the store change.
+#[derive(Default)]
+struct ListCache {
+    revision: Option<u64>,
+    filter: String,
+    /// Last filter that parsed, kept while the current one doesn't
+    query: Query,
+    /// Why the current filter doesn't parse
+    error: Option<String>,
+    images_only: bool,
+    source: Option<Selection>,
+    fuzzy: bool,
+    sort: prefs::SortOrder,
+    /// Indices into `Store::entries`, pinned first, then best match, then
+    /// newest or most used first
+    rows: Vec<usize>,
+    /// Content key of each row
+    keys: Vec<String>,
+    /// Row matching the fuzzy filter best
+    best: Option<usize>,
+}
+
+impl ListCache {
+    fn update(
+        &mut self,
+        store: &Store,
+        filter: &str,
+        images_only: bool,
+        source: Option<Selection>,
+        fuzzy: bool,
+        sort: prefs::SortOrder,
+    ) -> bool {
+        if self.revision == Some(store.revision())
+            && self.filter == filter
+            && self.images_only == images_only
+            && self.source == source
+            && self.fuzzy == fuzzy
+            && self.sort == sort
+        {
+            return false;
+        }
+        let refiltered = self.filter != filter || self.fuzzy != fuzzy;
+        if self.filter != filter {
+            match query::parse(filter) {
+                Ok(query) => {
+                    self.query = query;
+                    self.error = None;
+                }
+                Err(e) => self.error = Some(e.to_string()),
+            }
+        }
+        self.revision = Some(store.revision());
+        self.filter.zeroize();
+        self.filter.push_str(filter);
+        self.images_only = images_only;
+        self.source = source;
+        self.fuzzy = fuzzy;
+        self.sort = sort;
+        self.rows.clear();
+        self.keys.clear();
+
+        let items = store.entries();
+        let score = |e: &ClipboardEntry| {
+            if source.is_some_and(|s| s != e.source) {
+                return None;
+            }
+            if images_only && !matches!(e.content, ClipboardContent::ImageBase64(_)) {
+                return None;
+            }
+            self.query.score(e, fuzzy)
+        };
+        let mut scored: Vec<(usize, i32)> = (0..items.len())
+            .rev()
+            .filter(|&i| items[i].pinned)
+            .chain((0..items.len()).rev().filter(|&i| !items[i].pinned))
+            .filter_map(|i| score(&items[i]).map(|s| (i, s)))
+            .collect();
+        // Both stable, so equal scores stay in the order of the one before
+        if sort == prefs::SortOrder::Frecency {
+            let now = Utc::now();
+            scored.sort_by_cached_key(|&(i, _)| {
+                (!items[i].pinned, std::cmp::Reverse(items[i].frecency(now)))
+            });
+        }
+        if fuzzy {
+            scored.sort_by_key(|&(i, s)| (!items[i].pinned, std::cmp::Reverse(s)));
+        }
+        self.best = (fuzzy && !filter.trim().is_empty())
+            .then(|| {
+                let top = scored.iter().map(|&(_, s)| s).max()?;
+                scored.iter().position(|&(_, s)| s == top)
+            })
+            .flatten();
+        self.rows = scored.into_iter().map(|(i, _)| i).collect();
+        let keys = store.keys();
+        self.keys = self.rows.iter().map(|&i| keys[i].clone()).collect();
+        refiltered
+    }
+
+    fn clear(&mut self) {
+        self.revision = None;
+        self.best = None;
+        self.filter.zeroize();
+        self.query = Query::default();
+        self.error = None;
+        self.rows.clear();
+        self.keys.clear();
+    }
+}
+
+/// A restored entry waiting to be taken off the system clipboard again.
+struct PendingClear {
+    /// Key of the content ClipVault put there
+    placed: String,
+    /// What to put back, `None` leaves the clipboard empty
+    previous: Option<ClipboardContent>,
+    deadline: Instant,
+}
+
+impl Drop for PendingClear {
+    fn drop(&mut self) {
+        if let Some(ClipboardContent::Text(t)) = &mut self.previous {
+            t.zeroize();
+        }
+    }
+}
+
+#[derive(Default)]
+struct PassphraseForm {
+    old: String,
+    new: String,
+    confirm: String,
+    status: Option<Result<String, String>>,
+    // Keys being derived on a background thread, the form waits meanwhile
+    pending: Option<Receiver<anyhow::Result<NewPassphrase>>>,
+}
+
+impl PassphraseForm {
+    fn clear_inputs(&mut self) {
+        self.old.zeroize();
+        self.new.zeroize();
+        self.confirm.zeroize();
+    }
+
+    /// Starts deriving the keys for the entered passphrases.
+    fn start(&mut self, kdf: KdfParams) {
+        let (tx, rx) = bounded(1);
+        let (mut old, mut new) = (self.old.clone(), self.new.clone());
+        thread::spawn(move || {
+            let derived = NewPassphrase::derive(Some((&old, &kdf)), &new);
+            old.zeroize();
+            new.zeroize();
+            let _ = tx.send(derived);
+        });
+        self.pending = Some(rx);
+        self.status = None;
+    }
+
+    /// Changes the passphrase of `store` once the keys are ready. Polled every
+    /// frame, so closing the settings doesn't hold the change up.
+    fn finish(&mut self, store: &mut Store) {
+        let Some(rx) = &self.pending else {
+            return;
+        };
+        let res = match rx.try_recv() {
+            Ok(derived) => derived.and_then(|new| store.change_passphrase(&new)),
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => Err(anyhow::anyhow!("Key derivation failed")),
+        };
+        self.pending = None;
+        if res.is_ok() {
+            self.clear_inputs();
+        }
+        self.status = Some(
+            res.map(|_| "Passphrase changed.".to_owned())
+                .map_err(|e| e.to_string()),
+        );
+    }
+}
+
+/// History limits being edited. Lowering a limit deletes entries for good,
+/// so edits only take effect from Apply, after confirming what they remove.
+#[derive(Default)]
+struct RetentionForm {
+    draft: Option<prefs::Retention>,
+    confirm: bool,
 }
 
 impl ClipApp {
     pub fn new(
         tray: std::sync::Arc<tray::Tray>,
-        clipboard_rx: crossbeam::channel::Receiver<ClipboardEntry>,
-        store: Store,
         hotkey_rx: Receiver<HotkeyMsg>,
-        activate_rx: crossbeam::channel::Receiver<()>,
-        auto_launch: bool,
+        instance_rx: Receiver<InstanceMsg>,
+        prefs: prefs::Prefs,
     ) -> Self {
         Self {
             tray,
-            clipboard_rx: clipboard_rx,
-            store,
-            activate_rx,
+            clipboard_rx: None,
+            store: None,
+            lock_screen: LockScreen::new(),
+            ever_unlocked: false,
+            lock_requested: false,
+            quitting: false,
+            last_activity: Instant::now(),
+            instance_rx,
             filter: String::new(),
-            tex_cache: HashMap::new(),
+            textures: TextureCache::default(),
+            thumbnailer: Thumbnailer::spawn(),
             show_settings: false,
             show_timestamps: false,
+            source_filter: None,
             hotkey_rx,
-            window_visible: false,
-            auto_launch
+            window_visible: true,
+            capture: Arc::new(RwLock::new(prefs.capture.clone())),
+            prefs,
+            new_ignored_app: String::new(),
+            revealed: HashSet::new(),
+            pending_clear: None,
+            paste_target: None,
+            list: ListCache::default(),
+            row_heights: HashMap::new(),
+            selected: None,
+            nav_step: 0,
+            nav_tab: false,
+            scroll_to_selected: false,
+            preview: None,
+            editor: None,
+            hover: None,
+            tooltip_secs: None,
+            passphrase_form: PassphraseForm::default(),
+            retention_form: RetentionForm::default(),
+        }
+    }
+
+    fn unlock(&mut self, ctx: &egui::Context, unlocked: Unlocked) {
+        let store = match Store::open_or_create(
+            unlocked.key,
+            unlocked.nonce,
+            unlocked.kdf.clone(),
+            self.prefs.retention.clone(),
+        ) {
+            Ok(store) => store,
+            Err(e) => {
+                eprintln!("Failed to open history: {e}");
+                notify("Failed to open the clipboard history.");
+                return;
+            }
+        };
+
+        if self.clipboard_rx.is_none() {
+            let last_hash = store
+                .entries()
+                .last()
+                .map(|e| clipboard_entry_hash(&e.content));
+            let (tx, rx) = crossbeam::channel::unbounded();
+            spawn_watcher(tx, last_hash, self.capture.clone());
+            self.clipboard_rx = Some(rx);
+        }
+
+        self.store = Some(store);
+        self.last_activity = Instant::now();
+        set_window_layout(ctx, false);
+        // The first unlock happens at startup, after which ClipVault waits in the tray
+        if self.ever_unlocked {
+            self.show_main(ctx);
+        } else {
+            self.hide_main(ctx);
+        }
+        self.ever_unlocked = true;
+    }
+
+    fn lock(&mut self, ctx: &egui::Context) {
+        let Some(mut store) = self.store.take() else {
+            return;
+        };
+        self.clear_now();
+        if let Err(e) = store.force_save() {
+            eprintln!("Save failed: {e}");
+        }
+        drop(store);
+
+        self.textures.clear();
+        self.list.clear();
+        self.row_heights.clear();
+        self.selected = None;
+        self.preview = None;
+        self.editor = None;
+        self.hover = None;
+        self.revealed.clear();
+        self.filter.zeroize();
+        self.passphrase_form.clear_inputs();
+        self.passphrase_form.status = None;
+        self.passphrase_form.pending = None;
+        self.retention_form = RetentionForm::default();
+        self.show_settings = false;
+        self.lock_screen.reset();
+        set_window_layout(ctx, true);
+    }
+
+    fn clears(&self, entry: &ClipboardEntry) -> bool {
+        match self.prefs.auto_clear.scope {
+            prefs::ClearScope::Never => false,
+            prefs::ClearScope::Secrets => !entry.secrets.is_empty(),
+            prefs::ClearScope::All => true,
+        }
+    }
+
+    /// Arms the auto-clear for `entry`, which is about to replace `previous`
+    /// on the clipboard. A clear already pending keeps its original previous
+    /// content, so restoring several entries in a row still ends with what
+    /// was there before the first.
+    fn schedule_clear(&mut self, entry: &ClipboardEntry, previous: Option<ClipboardContent>) {
+        if !self.clears(entry) {
+            self.pending_clear = None;
+            return;
+        }
+
+        let c = &self.prefs.auto_clear;
+        let placed = content_key(&entry.content);
+        let previous = match self.pending_clear.take() {
+            Some(mut pending) => pending.previous.take(),
+            None => previous.filter(|_| c.restore_previous),
+        }
+        .filter(|p| content_key(p) != placed);
+        self.pending_clear = Some(PendingClear {
+            placed,
+            previous,
+            deadline: Instant::now() + Duration::from_secs(c.after_secs.max(1) as u64),
+        });
+    }
+
+    /// Runs the pending auto-clear, provided the clipboard still holds what
+    /// ClipVault put there. Returns the key of the previous content if it
+    /// was put back, which the store should expect the watcher to report.
+    fn clear_now(&mut self) -> Option<String> {
+        let pending = self.pending_clear.take()?;
+        match read_selection(Selection::Clipboard) {
+            Ok(Some(current)) if content_key(&current) == pending.placed => {}
+            Ok(_) => return None,
+            Err(e) => {
+                eprintln!("Failed to read the clipboard before clearing it: {e}");
+                return None;
+            }
+        }
+        // An empty text is as close to clearing as every platform gets
+        let empty = ClipboardContent::Text(String::new());
+        let content = pending.previous.as_ref().unwrap_or(&empty);
+        if let Err(e) = set_clipboard(content, &[], Selection::Clipboard) {
+            eprintln!("Failed to clear the clipboard: {e}");
+            return None;
+        }
+        pending.previous.as_ref().map(content_key)
+    }
+
+    fn tick_clear(&mut self) {
+        let left = self
+            .pending_clear
+            .as_ref()
+            .map(|p| p.deadline.saturating_duration_since(Instant::now()));
+        if left.is_some_and(|l| l.is_zero())
+            && let Some(key) = self.clear_now()
+            && let Some(store) = self.store.as_mut()
+        {
+            store.expect_echo(key);
+        }
+
+        let secs = left.filter(|l| !l.is_zero()).map(|l| l.as_secs() + 1);
+        if secs != self.tooltip_secs {
+            self.tooltip_secs = secs;
+            match secs {
+                Some(s) => self
+                    .tray
+                    .set_tooltip(&format!("ClipVault - clipboard clears in {s}s")),
+                None => self.tray.set_tooltip("ClipVault"),
+            }
         }
     }
 
     fn show_main(&mut self, ctx: &egui::Context) {
+        if !self.window_visible {
+            self.selected = None;
+            if self.prefs.paste.on_select {
+                self.paste_target = focused_window();
+            }
+        }
         ctx.send_viewport_cmd(egui::ViewportCommand::Visible(true));
         ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
         self.window_visible = true;
@@ -275,6 +648,58 @@ impl ClipApp {
     fn hide_main(&mut self, ctx: &egui::Context) {
         ctx.send_viewport_cmd(egui::ViewportCommand::Visible(false));
         self.window_visible = false;
+        self.preview = None;
+        self.editor = None;
+        self.revealed.clear();
+    }
+
+    /// Records the edited text, in place of the entry it was opened from or
+    /// next to it. The error says why it couldn't be saved.
+    fn save_edit(
+        &mut self,
+        store: &mut Store,
+        editor: &Editor,
+        as_new: bool,
+    ) -> Result<(), String> {
+        let Some(original) = store.get(&editor.key) else {
+            return Err("The entry is no longer in the history.".to_owned());
+        };
+        if editor.text.is_empty() {
+            return Err("An entry can't be empty.".to_owned());
+        }
+        let entry = ClipboardEntry {
+            ts: Utc::now(),
+            content: ClipboardContent::Text(editor.text.clone()),
+            pinned: false,
+            source: original.source,
+            // The other formats still hold the original text
+            targets: Vec::new(),
+            thumb: None,
+            secrets: Vec::new(),
+            expires: None,
+            // An edit in place carries on the history of the entry
+            usage: if as_new {
+                Usage::default()
+            } else {
+                original.usage.clone()
+            },
+        };
+        let Some(entry) = secrets::classify(entry, &self.prefs.secrets) else {
+            return Err("The text contains a secret that is never recorded.".to_owned());
+        };
+        if as_new {
+            store.put(entry);
+            return Ok(());
+        }
+        let key = store.replace(&editor.key, entry);
+        if self.revealed.remove(&editor.key) {
+            self.revealed.insert(key.clone());
+        }
+        if self.selected.as_ref() == Some(&editor.key) {
+            self.selected = Some(key);
+        }
+        self.row_heights.remove(&editor.key);
+        Ok(())
     }
 
     fn toggle_main(&mut self, ctx: &egui::Context) {
@@ -286,30 +711,328 @@ impl ClipApp {
     }
 }
 
-fn ensure_texture_for_b64(
-    cache: &mut HashMap<String, egui::TextureHandle>,
-    ctx: &egui::Context,
-    key: &str,
-    b64: &str,
-) {
-    if cache.contains_key(key) {
-        return;
-    }
-
-    if let Ok(img) = base64_to_imagedata(b64) {
-        let color: egui::ColorImage =
-            egui::ColorImage::from_rgba_unmultiplied([img.width, img.height], &img.bytes);
-        let tex: egui::TextureHandle = ctx.load_texture(
-            format!("thumb-{key}"),
-            color,
-            egui::TextureOptions::LINEAR, // smooth when scaled down
-        );
-        cache.insert(key.to_owned(), tex);
+fn set_window_layout(ctx: &egui::Context, locked: bool) {
+    let size = if locked { LOCKED_SIZE } else { UNLOCKED_SIZE };
+    ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(size.into()));
+    ctx.send_viewport_cmd(egui::ViewportCommand::Decorations(locked));
+}
+
+fn change_passphrase_ui(ui: &mut egui::Ui, form: &mut PassphraseForm, store: &Store) {
+    egui::CollapsingHeader::new("Change passphrase")
+        .default_open(false)
+        .show(ui, |ui| {
+            ui.add_enabled_ui(form.pending.is_none(), |ui| {
+                passphrase_fields_ui(ui, form, store.kdf());
+            });
+
+            if form.pending.is_some() {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label("Changing passphrase...");
+                });
+            }
+
+            match &form.status {
+                Some(Ok(msg)) => {
+                    ui.label(egui::RichText::new(msg).color(ui.visuals().selection.stroke.color));
+                }
+                Some(Err(msg)) => {
+                    ui.colored_label(ui.visuals().error_fg_color, msg);
+                }
+                None => {}
+            }
+        });
+}
+
+fn passphrase_fields_ui(ui: &mut egui::Ui, form: &mut PassphraseForm, kdf: &KdfParams) {
+    egui::Grid::new("passphrase_grid")
+        .num_columns(2)
+        .spacing([12.0, 4.0])
+        .show(ui, |ui| {
+            ui.label("Current");
+            ui.add(egui::TextEdit::singleline(&mut form.old).password(true));
+            ui.end_row();
+
+            ui.label("New");
+            ui.add(egui::TextEdit::singleline(&mut form.new).password(true));
+            ui.end_row();
+
+            ui.label("Repeat new");
+            ui.add(egui::TextEdit::singleline(&mut form.confirm).password(true));
+            ui.end_row();
+        });
+
+    if ui.button("Change passphrase").clicked() {
+        if form.new.is_empty() {
+            form.status = Some(Err("New passphrase cannot be empty.".to_owned()));
+        } else if form.new != form.confirm {
+            form.status = Some(Err("Passphrases don't match.".to_owned()));
+        } else {
+            form.start(kdf.clone());
+        }
     }
 }
 
-fn clickable_row(ui: &mut egui::Ui, text: &str) -> egui::Response {
-    let btn: egui::Button<'_> = egui::Button::new(egui::RichText::new(text)).frame(false);
+/// Editors for what the watcher records, returns `true` when one was changed.
+fn capture_ui(ui: &mut egui::Ui, c: &mut prefs::Capture) -> bool {
+    let before = c.clone();
+
+    ui.checkbox(&mut c.track_primary, "Record mouse selections (PRIMARY)")
+        .on_hover_text("Text selected with the mouse and pasted with middle-click");
+    ui.add_enabled(
+        c.track_primary,
+        egui::Checkbox::new(&mut c.sync_selections, "Keep CLIPBOARD and PRIMARY in sync"),
+    );
+    ui.checkbox(&mut c.rich_targets, "Keep rich formats (HTML, RTF, files, SVG)")
+        .on_hover_text("Restoring an entry pastes it with its formatting");
+    ui.add_enabled_ui(c.rich_targets, |ui| {
+        ui.horizontal(|ui| {
+            ui.label("Max KB per format");
+            ui.add(egui::DragValue::new(&mut c.max_target_kb).range(0..=1_000_000));
+            ui.label("per entry");
+            ui.add(egui::DragValue::new(&mut c.max_targets_kb).range(0..=1_000_000));
+        })
+        .response
+        .on_hover_text("0 = unlimited");
+    });
+
+    *c != before
+}
+
+fn privacy_ui(ui: &mut egui::Ui, c: &mut prefs::Capture, new_app: &mut String) -> bool {
+    let before = c.clone();
+
+    egui::CollapsingHeader::new("Privacy")
+        .default_open(false)
+        .show(ui, |ui| {
+            ui.checkbox(
+                &mut c.respect_secret_hints,
+                "Skip copies password managers mark as secret",
+            )
+            .on_hover_text("The marks are only seen on X11 and Wayland");
+
+            ui.label("Never record copies from these apps:");
+            let mut remove = None;
+            for (i, app) in c.ignored_apps.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    if ui.small_button("✖").on_hover_text("Remove").clicked() {
+                        remove = Some(i);
+                    }
+                    ui.label(app);
+                });
+            }
+            if let Some(i) = remove {
+                c.ignored_apps.remove(i);
+            }
+
+            ui.horizontal(|ui| {
+                let resp = ui.add(
+                    egui::TextEdit::singleline(new_app)
+                        .hint_text("WM_CLASS, process name or app id")
+                        .desired_width(220.0),
+                );
+                let submitted = resp.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                if (ui.button("Add").clicked() || submitted) && !new_app.trim().is_empty() {
+                    c.ignored_apps.push(new_app.trim().to_string());
+                    new_app.clear();
+                }
+            });
+        });
+
+    *c != before
+}
+
+fn secrets_ui(ui: &mut egui::Ui, s: &mut prefs::Secrets) -> bool {
+    let before = s.clone();
+
+    egui::CollapsingHeader::new("Secrets in copies")
+        .default_open(false)
+        .show(ui, |ui| {
+            egui::Grid::new("secrets_grid")
+                .num_columns(2)
+                .spacing([12.0, 4.0])
+                .show(ui, |ui| {
+                    for d in Detector::ALL {
+                        let mut action = s.action(d);
+                        ui.label(d.label());
+                        egui::ComboBox::from_id_salt(("secret_action", d))
+                            .selected_text(action.label())
+                            .show_ui(ui, |ui| {
+                                for a in SecretAction::ALL {
+                                    ui.selectable_value(&mut action, a, a.label());
+                                }
+                            });
+                        s.actions.insert(d, action);
+                        ui.end_row();
+                    }
+
+                    ui.label("Expire after (minutes)");
+                    ui.add(egui::DragValue::new(&mut s.expire_after_mins).range(1..=10_080));
+                    ui.end_row();
+                });
+        });
+
+    *s != before
+}
+
+fn auto_clear_ui(ui: &mut egui::Ui, c: &mut prefs::AutoClear) -> bool {
+    let before = c.clone();
+
+    ui.horizontal(|ui| {
+        ui.label("Clear the clipboard after restoring");
+        egui::ComboBox::from_id_salt("auto_clear_scope")
+            .selected_text(match c.scope {
+                prefs::ClearScope::Never => "never",
+                prefs::ClearScope::Secrets => "secrets",
+                prefs::ClearScope::All => "any entry",
+            })
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut c.scope, prefs::ClearScope::Never, "never");
+                ui.selectable_value(&mut c.scope, prefs::ClearScope::Secrets, "secrets");
+                ui.selectable_value(&mut c.scope, prefs::ClearScope::All, "any entry");
+            });
+    });
+    ui.add_enabled_ui(c.scope != prefs::ClearScope::Never, |ui| {
+        ui.horizontal(|ui| {
+            ui.label("after (seconds)");
+            ui.add(egui::DragValue::new(&mut c.after_secs).range(1..=3600));
+        });
+        ui.checkbox(&mut c.restore_previous, "Put the previous content back")
+            .on_hover_text("Otherwise the clipboard is left empty");
+    });
+
+    *c != before
+}
+
+/// Editors for the history limits, returns `true` when one was changed.
+fn retention_ui(ui: &mut egui::Ui, r: &mut prefs::Retention) -> bool {
+    let before = r.clone();
+
+    egui::CollapsingHeader::new("History limits (0 = unlimited)")
+        .default_open(false)
+        .show(ui, |ui| {
+            egui::Grid::new("retention_grid")
+                .num_columns(2)
+                .spacing([12.0, 4.0])
+                .show(ui, |ui| {
+                    ui.label("Max entries");
+                    ui.add(egui::DragValue::new(&mut r.max_entries).range(0..=100_000));
+                    ui.end_row();
+
+                    ui.label("Max age (days)");
+                    ui.add(egui::DragValue::new(&mut r.max_age_days).range(0..=3650));
+                    ui.end_row();
+
+                    ui.label("Max total size (MB)");
+                    ui.add(egui::DragValue::new(&mut r.max_total_mb).range(0..=100_000));
+                    ui.end_row();
+
+                    ui.label("Max text entries");
+                    ui.add(egui::DragValue::new(&mut r.max_text_entries).range(0..=100_000));
+                    ui.end_row();
+
+                    ui.label("Max image entries");
+                    ui.add(egui::DragValue::new(&mut r.max_image_entries).range(0..=100_000));
+                    ui.end_row();
+
+                    ui.label("Max image size (MB)");
+                    ui.add(egui::DragValue::new(&mut r.max_image_mb).range(0..=100_000));
+                    ui.end_row();
+                });
+        });
+
+    *r != before
+}
+
+/// Apply and Revert for edited history limits, shown while they differ from
+/// `current`. Returns the limits to apply once confirmed.
+fn retention_apply_ui(
+    ui: &mut egui::Ui,
+    form: &mut RetentionForm,
+    current: &prefs::Retention,
+    store: &Store,
+) -> Option<prefs::Retention> {
+    let draft = form.draft.clone().filter(|d| d != current)?;
+    let mut apply = false;
+
+    ui.horizontal(|ui| {
+        if form.confirm {
+            let n = store.evicted_by(&draft);
+            let noun = if n == 1 { "entry" } else { "entries" };
+            ui.colored_label(
+                ui.visuals().warn_fg_color,
+                format!("This deletes {n} {noun}."),
+            );
+            apply = ui.button("Delete and apply").clicked();
+            if ui.button("Cancel").clicked() {
+                form.confirm = false;
+            }
+        } else {
+            if ui.button("Apply").clicked() {
+                // Only ask when the new limits remove something
+                apply = store.evicted_by(&draft) == 0;
+                form.confirm = !apply;
+            }
+            if ui.button("Revert").clicked() {
+                form.draft = None;
+            }
+        }
+    });
+
+    if !apply {
+        return None;
+    }
+    form.confirm = false;
+    Some(draft)
+}
+
+fn pin_button(ui: &mut egui::Ui, pinned: bool) -> egui::Response {
+    let color = if pinned {
+        ui.visuals().selection.stroke.color
+    } else {
+        ui.visuals().weak_text_color()
+    };
+    ui.add(egui::Button::new(egui::RichText::new("📌").color(color)).frame(false))
+        .on_hover_text(if pinned { "Unpin" } else { "Pin" })
+}
+
+fn delete_button(ui: &mut egui::Ui) -> egui::Response {
+    let color = ui.visuals().weak_text_color();
+    ui.add(egui::Button::new(egui::RichText::new("🗑").color(color)).frame(false))
+        .on_hover_text("Delete (Del)")
+}
+
+fn edit_button(ui: &mut egui::Ui) -> egui::Response {
+    let color = ui.visuals().weak_text_color();
+    ui.add(egui::Button::new(egui::RichText::new("✏").color(color)).frame(false))
+        .on_hover_text("Edit (E)")
+}
+
+/// `text` laid out like a button label, with the parts in `ranges` marked.
+fn highlighted(ui: &egui::Ui, text: &str, ranges: &[Range<usize>]) -> egui::WidgetText {
+    if ranges.is_empty() {
+        return text.into();
+    }
+    let font = egui::TextStyle::Button.resolve(ui.style());
+    let normal = egui::TextFormat::simple(font, ui.visuals().text_color());
+    let hit = egui::TextFormat {
+        color: ui.visuals().strong_text_color(),
+        background: ui.visuals().selection.bg_fill.gamma_multiply(0.6),
+        ..normal.clone()
+    };
+    let mut job = egui::text::LayoutJob::default();
+    let mut at = 0;
+    for r in ranges {
+        job.append(&text[at..r.start], 0.0, normal.clone());
+        job.append(&text[r.clone()], 0.0, hit.clone());
+        at = r.end;
+    }
+    job.append(&text[at..], 0.0, normal);
+    job.into()
+}
+
+fn clickable_row(ui: &mut egui::Ui, text: impl Into<egui::WidgetText>) -> egui::Response {
+    let btn: egui::Button<'_> = egui::Button::new(text.into()).frame(false);
     let resp: egui::Response = ui
         .add_sized([ui.available_width(), ui.spacing().interact_size.y], btn)
         .on_hover_cursor(egui::CursorIcon::PointingHand)
@@ -347,11 +1070,21 @@ fn clickable_row(ui: &mut egui::Ui, text: &str) -> egui::Response {
 
 impl eframe::App for ClipApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        while let Ok(()) = self.activate_rx.try_recv() {
-            self.show_main(ctx);
+        ctx.request_repaint_after(std::time::Duration::from_millis(100));
+
+        while let Ok(msg) = self.instance_rx.try_recv() {
+            match msg {
+                InstanceMsg::Show => self.show_main(ctx),
+                InstanceMsg::Toggle => self.toggle_main(ctx),
+                InstanceMsg::Lock => self.lock(ctx),
+            }
         }
 
-        if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+        // Esc closes the editor and the preview first, then the window
+        if ctx.input(|i| i.key_pressed(egui::Key::Escape))
+            && self.editor.take().is_none()
+            && self.preview.take().is_none()
+        {
             self.hide_main(ctx);
             return;
         }
@@ -359,23 +1092,120 @@ impl eframe::App for ClipApp {
         while let Ok(msg) = self.hotkey_rx.try_recv() {
             match msg {
                 HotkeyMsg::ToggleWindow => self.toggle_main(ctx),
+                HotkeyMsg::Lock => self.lock(ctx),
             }
         }
 
         match self.tray.try_recv() {
             TrayEvent::OpenRequested => self.show_main(ctx),
+            TrayEvent::LockRequested => self.lock(ctx),
             TrayEvent::QuitRequested => {
+                self.quitting = true;
                 ctx.send_viewport_cmd(egui::ViewportCommand::Close);
-                self.store.force_save().ok();
+                if let Some(store) = self.store.as_mut() {
+                    store.force_save().ok();
+                }
                 return;
             }
             TrayEvent::None => {}
         }
 
-        ctx.request_repaint_after(std::time::Duration::from_millis(100));
+        self.tick_clear();
+
+        // Closing the startup prompt quits, later ones just hide to the tray
+        if ctx.input(|i| i.viewport().close_requested()) && self.ever_unlocked && !self.quitting {
+            ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+            self.hide_main(ctx);
+        }
+
+        let Some(mut store) = self.store.take() else {
+            // Nothing gets recorded while the vault is locked
+            if let Some(rx) = &self.clipboard_rx {
+                while rx.try_recv().is_ok() {}
+            }
+            if let Some(unlocked) = self.lock_screen.show(ctx) {
+                self.unlock(ctx, unlocked);
+            }
+            return;
+        };
+
+        if ctx.input(|i| !i.events.is_empty() || i.pointer.is_moving()) {
+            self.last_activity = Instant::now();
+        }
+        let idle_limit = self.prefs.lock_after_idle_mins;
+        if idle_limit > 0 && self.last_activity.elapsed().as_secs() >= idle_limit as u64 * 60 {
+            self.store = Some(store);
+            self.lock(ctx);
+            return;
+        }
+
+        self.history_ui(ctx, &mut store);
+        self.store = Some(store);
+
+        if std::mem::take(&mut self.lock_requested) {
+            self.lock(ctx);
+        }
+    }
+
+    fn raw_input_hook(&mut self, _ctx: &egui::Context, raw_input: &mut egui::RawInput) {
+        // The history list handles these itself, egui would move the focus
+        // between widgets with them
+        if self.store.is_none() || self.show_settings || self.editor.is_some() {
+            return;
+        }
+        let (mut step, mut tab) = (0, false);
+        raw_input.events.retain(|e| {
+            let egui::Event::Key {
+                key,
+                pressed,
+                modifiers,
+                ..
+            } = e
+            else {
+                return true;
+            };
+            match key {
+                egui::Key::ArrowUp if *pressed => step -= 1,
+                egui::Key::ArrowDown if *pressed => step += 1,
+                egui::Key::Tab if modifiers.is_none() => tab |= *pressed,
+                egui::Key::ArrowUp | egui::Key::ArrowDown => {}
+                _ => return true,
+            }
+            false
+        });
+        self.nav_step += step;
+        self.nav_tab |= tab;
+    }
 
-        while let Ok(entry) = self.clipboard_rx.try_recv() {
-            self.store.put(entry.ts, entry.content.clone());
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.clear_now();
+        if let Some(store) = self.store.as_mut() {
+            let _ = store.force_save();
+        }
+    }
+}
+
+impl ClipApp {
+    fn history_ui(&mut self, ctx: &egui::Context, store: &mut Store) {
+        if let Some(rx) = &self.clipboard_rx {
+            while let Ok(entry) = rx.try_recv() {
+                if let Some(entry) = secrets::classify(entry, &self.prefs.secrets) {
+                    store.put(entry);
+                }
+            }
+        }
+        for (key, thumb) in self.thumbnailer.finished() {
+            store.set_thumb(&key, thumb);
+        }
+        self.passphrase_form.finish(store);
+        if self.passphrase_form.pending.is_some() {
+            ctx.request_repaint_after(Duration::from_millis(100));
+        }
+        self.textures.begin_frame();
+        store.drop_expired();
+        if let Some(next) = store.entries().iter().filter_map(|e| e.expires).min() {
+            let left = (next - Utc::now()).to_std().unwrap_or_default();
+            ctx.request_repaint_after(left);
         }
 
         let filter_id = egui::Id::new("filter_input");
@@ -424,6 +1254,44 @@ impl eframe::App for ClipApp {
                     }
                 }
 
+                if self.prefs.capture.track_primary {
+                    egui::ComboBox::from_id_salt("source_filter")
+                        .width(84.0)
+                        .selected_text(match self.source_filter {
+                            None => "All",
+                            Some(Selection::Clipboard) => "Clipboard",
+                            Some(Selection::Primary) => "Primary",
+                        })
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.source_filter, None, "All");
+                            ui.selectable_value(
+                                &mut self.source_filter,
+                                Some(Selection::Clipboard),
+                                "Clipboard",
+                            );
+                            ui.selectable_value(
+                                &mut self.source_filter,
+                                Some(Selection::Primary),
+                                "Primary",
+                            );
+                        });
+                }
+
+                let sort = self.prefs.sort;
+                egui::ComboBox::from_id_salt("sort_order")
+                    .width(84.0)
+                    .selected_text(sort.label())
+                    .show_ui(ui, |ui| {
+                        for order in [prefs::SortOrder::Recency, prefs::SortOrder::Frecency] {
+                            ui.selectable_value(&mut self.prefs.sort, order, order.label());
+                        }
+                    })
+                    .response
+                    .on_hover_text("Frequent puts the entries used most, and lately, first");
+                if self.prefs.sort != sort {
+                    let _ = prefs::save(&self.prefs);
+                }
+
                 let icon_w = 26.0;
                 let h = ui.spacing().interact_size.y * 1.3;
                 let avail =
@@ -447,6 +1315,9 @@ impl eframe::App for ClipApp {
                     println!("Failed to load settings icon image");
                 }
             });
+            if let Some(error) = &self.list.error {
+                ui.colored_label(ui.visuals().error_fg_color, error);
+            }
         });
 
         if self.show_settings {
@@ -455,149 +1326,629 @@ impl eframe::App for ClipApp {
                 .collapsible(false)
                 .resizable(false)
                 .show(ctx, |ui| {
-                    let prev_auto = self.auto_launch;
-                    ui.checkbox(&mut self.auto_launch, "Auto-launch on login");
-                    if self.auto_launch != prev_auto {
-                        if let Err(e) = prefs::set_autostart(self.auto_launch) {
+                    let prev_auto = self.prefs.auto_launch;
+                    ui.checkbox(&mut self.prefs.auto_launch, "Auto-launch on login");
+                    if self.prefs.auto_launch != prev_auto {
+                        if let Err(e) = prefs::set_autostart(self.prefs.auto_launch) {
                             eprintln!("Failed to set autostart: {e}");
-                            self.auto_launch = prev_auto;
+                            self.prefs.auto_launch = prev_auto;
                         } else {
-                            let _ = prefs::save(&prefs::Prefs { auto_launch: self.auto_launch });
+                            let _ = prefs::save(&self.prefs);
                         }
                     }
                     ui.checkbox(&mut self.show_timestamps, "Show timestamps");
-                    if ui.button("Save now").clicked() {
-                        if let Err(e) = self.store.force_save() {
-                            eprintln!("Save failed: {e}");
+                    if ui
+                        .checkbox(&mut self.prefs.paste.on_select, "Paste on select")
+                        .on_hover_text(
+                            "Hide ClipVault and paste the entry into the window focused before it, X11 only",
+                        )
+                        .changed()
+                    {
+                        let _ = prefs::save(&self.prefs);
+                    }
+                    if ui
+                        .checkbox(&mut self.prefs.fuzzy_filter, "Fuzzy filter")
+                        .on_hover_text(
+                            "Match the words of the filter loosely and rank the results by how well they match",
+                        )
+                        .changed()
+                    {
+                        let _ = prefs::save(&self.prefs);
+                    }
+                    let capture_changed = capture_ui(ui, &mut self.prefs.capture)
+                        | privacy_ui(ui, &mut self.prefs.capture, &mut self.new_ignored_app);
+                    if capture_changed {
+                        if let Ok(mut capture) = self.capture.write() {
+                            *capture = self.prefs.capture.clone();
                         }
+                        let _ = prefs::save(&self.prefs);
+                    }
+                    if secrets_ui(ui, &mut self.prefs.secrets)
+                        | auto_clear_ui(ui, &mut self.prefs.auto_clear)
+                    {
+                        let _ = prefs::save(&self.prefs);
                     }
-                    if ui.button("Clear history").clicked() {
-                        self.store.clear();
-                        let _ = self.store.force_save();
+                    if ui.button("Save now").clicked()
+                        && let Err(e) = store.force_save()
+                    {
+                        eprintln!("Save failed: {e}");
+                    }
+                    if ui
+                        .button("Clear history")
+                        .on_hover_text("Pinned entries are kept")
+                        .clicked()
+                    {
+                        if let Err(e) = store.clear() {
+                            eprintln!("Failed to save the cleared history: {e}");
+                            notify("Clearing the history wasn't saved, it returns on next unlock.");
+                        }
+                        self.textures.retain(|k| store.contains(k));
+                        self.row_heights.retain(|k, _| store.contains(k));
+                    }
+
+                    ui.horizontal(|ui| {
+                        ui.label("Lock after idle (minutes, 0 = never)");
+                        let resp = ui.add(
+                            egui::DragValue::new(&mut self.prefs.lock_after_idle_mins)
+                                .range(0..=1440),
+                        );
+                        if resp.changed() {
+                            let _ = prefs::save(&self.prefs);
+                        }
+                    });
+                    if ui.button("Lock now").clicked() {
+                        self.lock_requested = true;
+                    }
+
+                    ui.separator();
+                    change_passphrase_ui(ui, &mut self.passphrase_form, store);
+
+                    let form = &mut self.retention_form;
+                    let draft = form
+                        .draft
+                        .get_or_insert_with(|| self.prefs.retention.clone());
+                    if retention_ui(ui, draft) {
+                        form.confirm = false;
+                    }
+                    if let Some(r) = retention_apply_ui(ui, form, &self.prefs.retention, store) {
+                        store.set_retention(r.clone());
+                        self.prefs.retention = r;
+                        let _ = prefs::save(&self.prefs);
                     }
                 });
         }
 
         let mut pending_restore: Option<ClipboardEntry> = None;
+        let mut pending_pin: Option<(String, bool)> = None;
+        let mut pending_delete: Option<String> = None;
+        let mut pending_reveal: Option<String> = None;
+        let mut pending_preview: Option<String> = None;
+        let mut pending_edit: Option<String> = None;
+        let mut pending_action: Option<(String, RowAction)> = None;
+        let mut restore_plain = false;
+        let mut hovered_row: Option<String> = None;
 
-        egui::CentralPanel::default().show(ctx, |ui| {
-            egui::ScrollArea::vertical().show(ui, |ui| {
-                ui.vertical_centered(|ui| {
-                    ui.set_max_width(300.0);
+        if let Some(pending) = &self.pending_clear {
+            let left = pending.deadline.saturating_duration_since(Instant::now());
+            let mut keep = false;
+            let mut now = false;
+            egui::TopBottomPanel::bottom("auto_clear").show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    let what = if pending.previous.is_some() {
+                        "Previous clipboard comes back"
+                    } else {
+                        "Clipboard clears"
+                    };
+                    ui.label(format!("⏱ {what} in {}s", left.as_secs() + 1));
+                    keep = ui
+                        .button("Keep")
+                        .on_hover_text("Leave the entry on the clipboard")
+                        .clicked();
+                    now = ui.button("Now").clicked();
                 });
+            });
+            if keep {
+                self.pending_clear = None;
+            } else if now && let Some(key) = self.clear_now() {
+                store.expect_echo(key);
+            }
+        }
 
-                let items = self.store.entries();
-                let id = egui::Id::new("images_only_toggle");
-                let images_only = ctx.data_mut(|d| d.get_persisted::<bool>(id).unwrap_or(false));
-                let q: String = self.filter.to_lowercase();
-                for idx in (0..items.len()).rev() {
-                    let entry: ClipboardEntry = items[idx].clone();
+        let id = egui::Id::new("images_only_toggle");
+        let images_only = ctx.data_mut(|d| d.get_persisted::<bool>(id).unwrap_or(false));
+        let source_filter = self
+            .source_filter
+            .filter(|_| self.prefs.capture.track_primary);
+        let fuzzy = self.prefs.fuzzy_filter;
+        let refiltered = self.list.update(
+            store,
+            &self.filter,
+            images_only,
+            source_filter,
+            fuzzy,
+            self.prefs.sort,
+        );
+        // The best fuzzy match is what Enter picks
+        if refiltered && let Some(best) = self.list.best {
+            self.selected = Some(self.list.keys[best].clone());
+            self.scroll_to_selected = true;
+        }
+        let items = store.entries();
+        let rows = &self.list.rows;
 
-                    if images_only {
-                        if !matches!(entry.content, ClipboardContent::ImageBase64(_)) {
-                            continue;
-                        }
-                    } else if !q.is_empty() {
-                        match &entry.content {
-                            ClipboardContent::Text(t) => {
-                                if !t.to_lowercase().contains(&q) {
-                                    continue;
-                                }
-                            }
-                            ClipboardContent::ImageBase64(_) => continue,
-                        }
+        // Keyboard navigation, only while the settings window and the editor are closed
+        let typing = ctx.memory(|m| m.has_focus(filter_id));
+        let mut step = std::mem::take(&mut self.nav_step);
+        let tab = std::mem::take(&mut self.nav_tab);
+        let keys = ctx.input(|i| {
+            let plain = |key| !typing && i.modifiers.is_none() && i.key_pressed(key);
+            use egui::Key::*;
+            let alt_pick = [Num1, Num2, Num3, Num4, Num5, Num6, Num7, Num8, Num9]
+                .into_iter()
+                .position(|k| i.modifiers.alt && i.key_pressed(k));
+            (
+                plain(egui::Key::J) as isize - plain(egui::Key::K) as isize,
+                i.key_pressed(egui::Key::Enter),
+                !typing && i.key_pressed(egui::Key::Delete),
+                plain(egui::Key::P),
+                plain(egui::Key::Space),
+                plain(egui::Key::E),
+                alt_pick,
+            )
+        });
+        let (jk, enter, delete_key, pin_key, space_key, edit_key, alt_pick) =
+            if self.show_settings || self.editor.is_some() {
+                (0, false, false, false, false, false, None)
+            } else {
+                keys
+            };
+        step += jk;
+
+        let mut selected = self
+            .selected
+            .as_deref()
+            .and_then(|k| self.list.keys.iter().position(|r| r == k));
+        if step != 0 && !rows.is_empty() {
+            selected = Some(match selected {
+                Some(p) => p.saturating_add_signed(step).min(rows.len() - 1),
+                None => 0,
+            });
+            self.scroll_to_selected = true;
+        }
+        if tab {
+            if typing {
+                ctx.memory_mut(|m| m.surrender_focus(filter_id));
+                if selected.is_none() && !rows.is_empty() {
+                    selected = Some(0);
+                    self.scroll_to_selected = true;
+                }
+            } else {
+                ctx.memory_mut(|m| m.request_focus(filter_id));
+            }
+        }
+        self.selected = selected.map(|p| self.list.keys[p].clone());
+
+        // Enter in the filter picks the first match
+        let picked = alt_pick
+            .or(selected.filter(|_| enter))
+            .or_else(|| (enter && typing).then_some(0));
+        if let Some(&idx) = picked.and_then(|p| rows.get(p)) {
+            pending_restore = Some(items[idx].clone());
+        }
+        if let Some(p) = selected {
+            if space_key {
+                pending_preview = Some(self.list.keys[p].clone());
+            }
+            // Like the edit button, a masked entry has to be revealed first
+            let key = &self.list.keys[p];
+            if edit_key
+                && (!self.prefs.secrets.masks(&items[rows[p]].secrets)
+                    || self.revealed.contains(key))
+            {
+                pending_edit = Some(key.clone());
+            }
+            if pin_key {
+                pending_pin = Some((self.list.keys[p].clone(), !items[rows[p]].pinned));
+            }
+            if delete_key {
+                pending_delete = self.selected.clone();
+                // Keep a selection, on the row that moves into its place
+                self.selected = self
+                    .list
+                    .keys
+                    .get(p + 1)
+                    .or(p.checked_sub(1).and_then(|q| self.list.keys.get(q)))
+                    .cloned();
+            }
+        }
+
+        // Rows that were never on screen yet count with a guess
+        let heights: Vec<f32> = self
+            .list
+            .keys
+            .iter()
+            .map(|k| self.row_heights.get(k).copied().unwrap_or(ROW_HEIGHT_GUESS))
+            .collect();
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            egui::ScrollArea::vertical()
+                .auto_shrink(false)
+                .show_viewport(ui, |ui, viewport| {
+                    ui.set_height(heights.iter().sum());
+                    let origin = ui.max_rect().min;
+
+                    if let Some(p) = selected
+                        && std::mem::take(&mut self.scroll_to_selected)
+                    {
+                        let top: f32 = heights[..p].iter().sum();
+                        let rect = egui::Rect::from_min_size(
+                            origin + egui::vec2(0.0, top),
+                            egui::vec2(ui.available_width(), heights[p]),
+                        );
+                        ui.scroll_to_rect(rect, None);
                     }
 
-                    let (_key, tex_opt) = match &entry.content {
-                        ClipboardContent::ImageBase64(b64) => {
-                            let k = content_key(&entry.content);
-                            ensure_texture_for_b64(&mut self.tex_cache, ctx, &k, b64);
-                            let handle = self.tex_cache.get(&k).cloned();
-                            (Some(k), handle)
-                        }
-                        _ => (None, None),
-                    };
+                    // Only the rows overlapping the viewport are laid out
+                    let (mut first, mut top) = (0, 0.0);
+                    while first < heights.len() && top + heights[first] < viewport.min.y {
+                        top += heights[first];
+                        first += 1;
+                    }
+                    let rect = egui::Rect::from_min_max(
+                        origin + egui::vec2(0.0, top),
+                        egui::pos2(ui.max_rect().max.x, f32::INFINITY),
+                    );
+                    ui.scope_builder(egui::UiBuilder::new().max_rect(rect), |ui| {
+                        for (pos, &idx) in rows.iter().enumerate().skip(first) {
+                            let row_top = ui.cursor().top();
+                            if row_top - origin.y > viewport.max.y {
+                                break;
+                            }
+                            let entry: &ClipboardEntry = &items[idx];
+                            let key: &String = &self.list.keys[pos];
 
-                    ui.horizontal(|ui| {
-                        ui.set_max_width(500.0);
-                        if self.show_timestamps {
-                            ui.label(
-                                egui::RichText::new(format!("[{}]", entry.ts.format("%H:%M:%S")))
-                                    .monospace()
-                                    .color(egui::Color32::GRAY),
-                            );
-                        }
+                            if entry.pinned && pos == 0 {
+                                ui.label(egui::RichText::new("📌 Pinned").strong());
+                            } else if !entry.pinned && pos > 0 && items[rows[pos - 1]].pinned {
+                                ui.separator();
+                            }
 
-                        match (&entry.content, tex_opt) {
-                            (ClipboardContent::Text(t), _) => {
-                                let display_text = {
-                                    let mut s = t.clone();
-                                    if let Some((cut, _)) = s.match_indices('\n').nth(4) {
-                                        s.truncate(cut);
-                                        s.push_str("\n…");
+                            let tex_opt = match &entry.content {
+                                ClipboardContent::ImageBase64(b64) => match &entry.thumb {
+                                    Some(thumb) => self.textures.get(ctx, key, thumb),
+                                    None => {
+                                        self.thumbnailer.request(key, b64);
+                                        None
                                     }
-                                    s
-                                };
-
-                                let resp = clickable_row(ui, &display_text);
+                                },
+                                _ => None,
+                            };
 
-                                if resp.clicked() {
-                                    pending_restore = Some(entry.clone());
+                            let row = ui.horizontal(|ui| {
+                                ui.set_max_width(500.0);
+                                if pin_button(ui, entry.pinned).clicked() {
+                                    pending_pin = Some((key.clone(), !entry.pinned));
                                 }
-                            }
-                            (ClipboardContent::ImageBase64(_), Some(tex)) => {
-                                let [w, h] = tex.size();
-                                let (w, h) = (w as f32, h as f32);
-                                let max_w = 512.0;
-                                let scale = (max_w / w).min(1.0);
-                                let size = egui::vec2(w * scale, h * scale);
-                                let sized = egui::load::SizedTexture { id: tex.id(), size };
-                                let resp = ui
-                                    .add(egui::Image::new(sized).sense(egui::Sense::click()))
-                                    .on_hover_text("Click to copy")
-                                    .on_hover_cursor(egui::CursorIcon::PointingHand);
-                                let visuals = ui.visuals();
-                                let rounding = egui::CornerRadius::same(6);
-                                let stroke = if resp.hovered() {
-                                    egui::Stroke::new(1.5, visuals.widgets.hovered.fg_stroke.color)
-                                } else {
-                                    egui::Stroke::new(
-                                        1.0,
-                                        visuals
-                                            .widgets
-                                            .inactive
-                                            .fg_stroke
-                                            .color
-                                            .gamma_multiply(0.25),
+                                if delete_button(ui).clicked() {
+                                    pending_delete = Some(key.clone());
+                                }
+                                if self.show_timestamps {
+                                    ui.label(
+                                        egui::RichText::new(format!(
+                                            "[{}]",
+                                            entry.ts.format("%H:%M:%S")
+                                        ))
+                                        .monospace()
+                                        .color(egui::Color32::GRAY),
+                                    );
+                                }
+                                if entry.source == Selection::Primary {
+                                    ui.label(egui::RichText::new("🖱").color(egui::Color32::GRAY))
+                                        .on_hover_text("Mouse selection (PRIMARY)");
+                                }
+                                if !entry.targets.is_empty() {
+                                    let formats: Vec<&str> =
+                                        entry.targets.iter().map(|t| t.mime.as_str()).collect();
+                                    ui.label(
+                                        egui::RichText::new("rich")
+                                            .small()
+                                            .color(egui::Color32::GRAY),
                                     )
+                                    .on_hover_text(format!(
+                                        "Also copied as {}\nShift+click to paste plain",
+                                        formats.join(", ")
+                                    ));
+                                }
+                                let masked = self.prefs.secrets.masks(&entry.secrets);
+                                let revealed = self.revealed.contains(key);
+                                if !entry.secrets.is_empty() {
+                                    let labels: Vec<&str> =
+                                        entry.secrets.iter().map(|d| d.label()).collect();
+                                    let mut hover = format!("Contains: {}", labels.join(", "));
+                                    if let Some(t) = entry.expires {
+                                        let mins = (t - Utc::now()).num_minutes().max(0) + 1;
+                                        hover.push_str(&format!("\nExpires in {mins} min"));
+                                    }
+                                    ui.label(egui::RichText::new("🔑").color(egui::Color32::GOLD))
+                                        .on_hover_text(hover);
+                                }
+                                if masked {
+                                    let eye = egui::RichText::new("👁").color(if revealed {
+                                        ui.visuals().selection.stroke.color
+                                    } else {
+                                        ui.visuals().weak_text_color()
+                                    });
+                                    if ui
+                                        .add(egui::Button::new(eye).frame(false))
+                                        .on_hover_text(if revealed { "Hide" } else { "Reveal" })
+                                        .clicked()
+                                    {
+                                        pending_reveal = Some(key.clone());
+                                    }
+                                }
+                                if matches!(entry.content, ClipboardContent::Text(_))
+                                    && (!masked || revealed)
+                                    && edit_button(ui).clicked()
+                                {
+                                    pending_edit = Some(key.clone());
+                                }
+
+                                let content = match (&entry.content, tex_opt) {
+                                    (ClipboardContent::Text(t), _) if masked && !revealed => {
+                                        let resp = clickable_row(ui, secrets::masked(t));
+                                        if resp.clicked() {
+                                            pending_restore = Some(entry.clone());
+                                        }
+                                        resp
+                                    }
+                                    (ClipboardContent::Text(t), _) => {
+                                        let display_text = {
+                                            let mut s = t.clone();
+                                            if let Some((cut, _)) = s.match_indices('\n').nth(4) {
+                                                s.truncate(cut);
+                                                s.push_str("\n…");
+                                            }
+                                            s
+                                        };
+
+                                        let ranges =
+                                            self.list.query.highlights(&display_text, fuzzy);
+                                        let resp = clickable_row(
+                                            ui,
+                                            highlighted(ui, &display_text, &ranges),
+                                        );
+
+                                        if resp.clicked() {
+                                            pending_restore = Some(entry.clone());
+                                        }
+                                        resp
+                                    }
+                                    (ClipboardContent::ImageBase64(_), Some(tex)) => {
+                                        let [w, h] = tex.size();
+                                        let (w, h) = (w as f32, h as f32);
+                                        let max_w = 512.0;
+                                        let scale = (max_w / w).min(1.0);
+                                        let size = egui::vec2(w * scale, h * scale);
+                                        let sized = egui::load::SizedTexture { id: tex.id(), size };
+                                        let resp = ui
+                                            .add(
+                                                egui::Image::new(sized).sense(egui::Sense::click()),
+                                            )
+                                            .on_hover_text("Click to copy")
+                                            .on_hover_cursor(egui::CursorIcon::PointingHand);
+                                        let visuals = ui.visuals();
+                                        let rounding = egui::CornerRadius::same(6);
+                                        let stroke = if resp.hovered() {
+                                            egui::Stroke::new(
+                                                1.5,
+                                                visuals.widgets.hovered.fg_stroke.color,
+                                            )
+                                        } else {
+                                            egui::Stroke::new(
+                                                1.0,
+                                                visuals
+                                                    .widgets
+                                                    .inactive
+                                                    .fg_stroke
+                                                    .color
+                                                    .gamma_multiply(0.25),
+                                            )
+                                        };
+                                        ui.painter().rect_stroke(
+                                            resp.rect.expand(2.0),
+                                            rounding,
+                                            stroke,
+                                            StrokeKind::Inside,
+                                        );
+                                        if resp.clicked() {
+                                            pending_restore = Some(entry.clone());
+                                        }
+                                        resp
+                                    }
+
+                                    (ClipboardContent::ImageBase64(b64), None) => ui.add(
+                                        egui::Label::new(format!("<image {} bytes>", b64.len()))
+                                            .sense(egui::Sense::click()),
+                                    ),
                                 };
+                                content.context_menu(|ui| {
+                                    let action = menu::row_menu(ui, entry, masked && !revealed);
+                                    if let Some(action) = action {
+                                        pending_action = Some((key.clone(), action));
+                                    }
+                                });
+                            });
+
+                            if selected == Some(pos) {
                                 ui.painter().rect_stroke(
-                                    resp.rect.expand(2.0),
-                                    rounding,
-                                    stroke,
-                                    StrokeKind::Inside,
+                                    row.response.rect.expand(4.0),
+                                    egui::CornerRadius::same(6),
+                                    egui::Stroke::new(2.0, ui.visuals().selection.stroke.color),
+                                    StrokeKind::Outside,
                                 );
-                                if resp.clicked() {
-                                    pending_restore = Some(entry.clone());
-                                }
+                            } else if delete_key
+                                && selected.is_none()
+                                && row.response.contains_pointer()
+                            {
+                                // Without a selection, Delete removes the row under the pointer
+                                pending_delete = Some(key.clone());
                             }
-
-                            (ClipboardContent::ImageBase64(b64), None) => {
-                                ui.label(format!("<image {} bytes>", b64.len()));
+                            if row.response.contains_pointer() {
+                                hovered_row = Some(key.clone());
                             }
+                            self.row_heights
+                                .insert(key.clone(), ui.cursor().top() - row_top);
                         }
                     });
+                });
+        });
+
+        if let Some((key, action)) = pending_action {
+            let entry = store.get(&key);
+            match action {
+                RowAction::Copy => pending_restore = entry.cloned(),
+                RowAction::CopyPlain => {
+                    pending_restore = entry.cloned();
+                    restore_plain = true;
+                }
+                RowAction::Pin(pinned) => pending_pin = Some((key, pinned)),
+                RowAction::Delete => pending_delete = Some(key),
+                RowAction::Edit => pending_edit = Some(key),
+                RowAction::SaveImage => {
+                    if let Some(ClipboardEntry {
+                        content: ClipboardContent::ImageBase64(b64),
+                        ts,
+                        ..
+                    }) = entry
+                    {
+                        match img::save_png(b64, *ts) {
+                            Ok(path) => notify(&format!("Image saved to {}", path.display())),
+                            Err(e) => {
+                                eprintln!("Failed to save the image: {e}");
+                                notify("Could not save the image.");
+                            }
+                        }
+                    }
                 }
+                RowAction::CopyDataUri => {
+                    if let Some(ClipboardEntry {
+                        content: ClipboardContent::ImageBase64(b64),
+                        ..
+                    }) = entry
+                    {
+                        let uri = ClipboardContent::Text(format!("data:image/png;base64,{b64}"));
+                        if let Err(e) = set_clipboard(&uri, &[], Selection::Clipboard) {
+                            eprintln!("Failed to copy the data URI: {e}");
+                        }
+                    }
+                }
+                RowAction::OpenUrl(url) => {
+                    if let Err(e) = open::that_detached(&url) {
+                        eprintln!("Failed to open {url}: {e}");
+                    }
+                }
+                RowAction::OpenPath(path) => {
+                    if let Err(e) = open::that_detached(menu::folder_of(&path)) {
+                        eprintln!("Failed to open {}: {e}", path.display());
+                    }
+                }
+            }
+        }
+
+        // Resting on a row opens it in the preview, once per visit
+        match (&mut self.hover, hovered_row) {
+            (Some((key, since, shown)), Some(row)) if *key == row => {
+                if !*shown && since.elapsed() >= PREVIEW_HOVER_DELAY {
+                    *shown = true;
+                    if self.preview.as_ref().is_none_or(|p| p.key != row) {
+                        pending_preview = Some(row);
+                    }
+                }
+            }
+            (hover, row) => *hover = row.map(|r| (r, Instant::now(), false)),
+        }
+
+        // Space on the row already in the preview closes it
+        if let Some(key) = pending_preview {
+            self.preview = match self.preview.take() {
+                Some(p) if p.key == key && space_key => None,
+                _ => store.get(&key).map(|e| Preview::open(ctx, key, e)),
+            };
+        }
+        if let Some(preview) = &mut self.preview {
+            let open = store.get(&preview.key).is_some_and(|entry| {
+                let masked = self.prefs.secrets.masks(&entry.secrets)
+                    && !self.revealed.contains(&preview.key);
+                preview.show(ctx, entry, masked)
             });
-        });
+            if !open {
+                self.preview = None;
+            }
+        }
+
+        if let Some(key) = pending_edit
+            && let Some(ClipboardEntry {
+                content: ClipboardContent::Text(text),
+                ..
+            }) = store.get(&key)
+        {
+            self.editor = Some(Editor::open(key, text));
+        }
+        if let Some(mut editor) = self.editor.take() {
+            let done = match editor.show(ctx) {
+                None => false,
+                Some(EditorAction::Cancel) => true,
+                Some(action) => {
+                    let as_new = matches!(action, EditorAction::SaveAsNew);
+                    self.save_edit(store, &editor, as_new)
+                        .map_err(|e| editor.error = Some(e))
+                        .is_ok()
+                }
+            };
+            if !done {
+                self.editor = Some(editor);
+            }
+        }
+
+        if let Some(key) = pending_delete {
+            store.remove(&key);
+            self.textures.remove(&key);
+            self.row_heights.remove(&key);
+        }
+
+        if let Some(key) = pending_reveal
+            && !self.revealed.remove(&key)
+        {
+            self.revealed.insert(key);
+        }
+
+        if let Some((key, pinned)) = pending_pin {
+            store.set_pinned(&key, pinned);
+        }
 
         if let Some(entry) = pending_restore {
-            let _ = set_clipboard(&entry.content);
-            let now = Utc::now();
-            self.store.put(now, entry.content.clone());
+            // Shift+click pastes the bare text or image, without HTML, RTF, ...
+            let targets: &[Target] = if restore_plain || ctx.input(|i| i.modifiers.shift) {
+                &[]
+            } else {
+                &entry.targets
+            };
+            // Only read when it may have to be put back
+            let previous = (self.clears(&entry)
+                && self.prefs.auto_clear.restore_previous
+                && self.pending_clear.is_none())
+            .then(|| read_selection(Selection::Clipboard).ok().flatten())
+            .flatten();
+            if set_clipboard(&entry.content, targets, Selection::Clipboard).is_ok() {
+                self.schedule_clear(&entry, previous);
+                if self.prefs.paste.on_select
+                    && let Some(window) = self.paste_target.take()
+                {
+                    self.hide_main(ctx);
+                    paste_into(window, self.prefs.paste.terminals.clone());
+                }
+            }
+            store.restore(entry);
         }
     }
-    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
-        let _ = self.store.force_save();
-    }
 }
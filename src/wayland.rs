@@ -0,0 +1,466 @@
+use anyhow::{Result, anyhow, bail};
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::fd::{AsFd, OwnedFd};
+use std::os::unix::net::UnixStream;
+use std::thread;
+use std::time::Duration;
+
+use wayland_client::backend::ObjectId;
+use wayland_client::globals::{GlobalList, GlobalListContents, registry_queue_init};
+use wayland_client::protocol::{wl_registry, wl_seat::WlSeat};
+use wayland_client::{
+    Connection, Dispatch, EventQueue, Proxy, QueueHandle, delegate_noop, event_created_child,
+};
+use wayland_protocols::ext::data_control::v1::client::{
+    ext_data_control_device_v1 as ext_device, ext_data_control_manager_v1 as ext_manager,
+    ext_data_control_offer_v1 as ext_offer, ext_data_control_source_v1 as ext_source,
+};
+use wayland_protocols_wlr::data_control::v1::client::{
+    zwlr_data_control_device_v1 as wlr_device, zwlr_data_control_manager_v1 as wlr_manager,
+    zwlr_data_control_offer_v1 as wlr_offer, zwlr_data_control_source_v1 as wlr_source,
+};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::{
+    zwlr_foreign_toplevel_handle_v1 as toplevel_handle,
+    zwlr_foreign_toplevel_manager_v1 as toplevel_manager,
+};
+
+use crate::clip::{ChangeSource, SelectionData, read_offered};
+use crate::prefs::Capture;
+use crate::types::Selection;
+
+// A source that never writes would otherwise hang the watcher
+const RECEIVE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Whether this is a Wayland session, where the data-control protocol is the
+/// only way to see the clipboard without having focus.
+pub fn available() -> bool {
+    std::env::var_os("WAYLAND_DISPLAY").is_some()
+}
+
+// ext-data-control is the standardised successor of wlr-data-control, their
+// requests and events are identical so each object wraps either of them.
+enum Manager {
+    Ext(ext_manager::ExtDataControlManagerV1),
+    Wlr(wlr_manager::ZwlrDataControlManagerV1),
+}
+
+enum Device {
+    Ext(ext_device::ExtDataControlDeviceV1),
+    Wlr(wlr_device::ZwlrDataControlDeviceV1),
+}
+
+enum Offer {
+    Ext(ext_offer::ExtDataControlOfferV1),
+    Wlr(wlr_offer::ZwlrDataControlOfferV1),
+}
+
+enum Source {
+    Ext(ext_source::ExtDataControlSourceV1),
+    Wlr(wlr_source::ZwlrDataControlSourceV1),
+}
+
+impl Manager {
+    fn bind(globals: &GlobalList, qh: &QueueHandle<State>) -> Result<Self> {
+        if let Ok(manager) = globals.bind(qh, 1..=1, ()) {
+            return Ok(Self::Ext(manager));
+        }
+        globals
+            .bind(qh, 1..=2, ())
+            .map(Self::Wlr)
+            .map_err(|_| anyhow!("Compositor supports neither ext- nor wlr-data-control"))
+    }
+
+    fn get_data_device(&self, seat: &WlSeat, qh: &QueueHandle<State>) -> Device {
+        match self {
+            Self::Ext(m) => Device::Ext(m.get_data_device(seat, qh, ())),
+            Self::Wlr(m) => Device::Wlr(m.get_data_device(seat, qh, ())),
+        }
+    }
+
+    fn create_data_source(&self, qh: &QueueHandle<State>) -> Source {
+        match self {
+            Self::Ext(m) => Source::Ext(m.create_data_source(qh, ())),
+            Self::Wlr(m) => Source::Wlr(m.create_data_source(qh, ())),
+        }
+    }
+}
+
+impl Device {
+    fn set_selection(&self, selection: Selection, source: &Source) -> Result<()> {
+        match (self, source, selection) {
+            (Self::Ext(d), Source::Ext(s), Selection::Clipboard) => d.set_selection(Some(s)),
+            (Self::Ext(d), Source::Ext(s), Selection::Primary) => d.set_primary_selection(Some(s)),
+            (Self::Wlr(d), Source::Wlr(s), Selection::Clipboard) => d.set_selection(Some(s)),
+            (Self::Wlr(d), Source::Wlr(s), Selection::Primary) => {
+                if d.version() < 2 {
+                    bail!("Compositor can't set the primary selection");
+                }
+                d.set_primary_selection(Some(s))
+            }
+            _ => unreachable!("device and source come from the same manager"),
+        }
+        Ok(())
+    }
+
+    fn destroy(&self) {
+        match self {
+            Self::Ext(d) => d.destroy(),
+            Self::Wlr(d) => d.destroy(),
+        }
+    }
+}
+
+impl Offer {
+    fn receive(&self, mime: &str, fd: std::os::fd::BorrowedFd<'_>) {
+        match self {
+            Self::Ext(o) => o.receive(mime.to_string(), fd),
+            Self::Wlr(o) => o.receive(mime.to_string(), fd),
+        }
+    }
+
+    fn destroy(&self) {
+        match self {
+            Self::Ext(o) => o.destroy(),
+            Self::Wlr(o) => o.destroy(),
+        }
+    }
+}
+
+impl Source {
+    fn offer(&self, mime: &str) {
+        match self {
+            Self::Ext(s) => s.offer(mime.to_string()),
+            Self::Wlr(s) => s.offer(mime.to_string()),
+        }
+    }
+
+    fn destroy(&self) {
+        match self {
+            Self::Ext(s) => s.destroy(),
+            Self::Wlr(s) => s.destroy(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    // Offers announced by the compositor, with their MIME types, until the
+    // next selection event
+    offers: HashMap<ObjectId, (Offer, Vec<String>)>,
+    selections: HashMap<Selection, (Offer, Vec<String>)>,
+    changed: VecDeque<Selection>,
+    finished: bool,
+    // What our own source hands out, by MIME type
+    serving: HashMap<String, Vec<u8>>,
+    cancelled: bool,
+    toplevels: HashMap<ObjectId, Toplevel>,
+}
+
+#[derive(Default)]
+struct Toplevel {
+    app_id: String,
+    activated: bool,
+}
+
+impl State {
+    fn data_offer(&mut self, id: ObjectId, offer: Offer) {
+        self.offers.insert(id, (offer, Vec::new()));
+    }
+
+    fn offer_mime(&mut self, id: &ObjectId, mime: String) {
+        if let Some((_, mimes)) = self.offers.get_mut(id) {
+            mimes.push(mime);
+        }
+    }
+
+    fn selection(&mut self, selection: Selection, id: Option<ObjectId>) {
+        if let Some((old, _)) = self.selections.remove(&selection) {
+            old.destroy();
+        }
+        if let Some(offer) = id.and_then(|id| self.offers.remove(&id)) {
+            self.selections.insert(selection, offer);
+        }
+        // Every offer is announced right before the selection event it is
+        // for, so whatever is still pending was superseded
+        for (_, (offer, _)) in self.offers.drain() {
+            offer.destroy();
+        }
+        self.changed.push_back(selection);
+    }
+
+    fn send(&self, mime: &str, fd: OwnedFd) {
+        let Some(data) = self.serving.get(mime) else {
+            return;
+        };
+        if let Err(e) = File::from(fd).write_all(data) {
+            eprintln!("Failed to hand out the clipboard: {e}");
+        }
+    }
+}
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for State {
+    fn event(
+        _: &mut Self,
+        _: &wl_registry::WlRegistry,
+        _: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+delegate_noop!(State: ignore WlSeat);
+
+impl Dispatch<toplevel_manager::ZwlrForeignToplevelManagerV1, ()> for State {
+    // Each toplevel reports its details through its own handle
+    fn event(
+        _: &mut Self,
+        _: &toplevel_manager::ZwlrForeignToplevelManagerV1,
+        _: toplevel_manager::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+
+    event_created_child!(State, toplevel_manager::ZwlrForeignToplevelManagerV1, [
+        toplevel_manager::EVT_TOPLEVEL_OPCODE => (toplevel_handle::ZwlrForeignToplevelHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<toplevel_handle::ZwlrForeignToplevelHandleV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        handle: &toplevel_handle::ZwlrForeignToplevelHandleV1,
+        event: toplevel_handle::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            toplevel_handle::Event::AppId { app_id } => {
+                state.toplevels.entry(handle.id()).or_default().app_id = app_id;
+            }
+            toplevel_handle::Event::State { state: flags } => {
+                let activated = toplevel_handle::State::Activated as u32;
+                state.toplevels.entry(handle.id()).or_default().activated = flags
+                    .chunks_exact(4)
+                    .any(|f| u32::from_ne_bytes(f.try_into().unwrap()) == activated);
+            }
+            toplevel_handle::Event::Closed => {
+                state.toplevels.remove(&handle.id());
+                handle.destroy();
+            }
+            _ => {}
+        }
+    }
+}
+
+macro_rules! data_control_dispatch {
+    ($variant:ident, $manager:ty, $device:ident, $device_ty:ty, $offer:ident, $offer_ty:ty, $source:ident, $source_ty:ty) => {
+        delegate_noop!(State: $manager);
+
+        impl Dispatch<$device_ty, ()> for State {
+            fn event(
+                state: &mut Self,
+                _: &$device_ty,
+                event: $device::Event,
+                _: &(),
+                _: &Connection,
+                _: &QueueHandle<Self>,
+            ) {
+                match event {
+                    $device::Event::DataOffer { id } => state.data_offer(id.id(), Offer::$variant(id)),
+                    $device::Event::Selection { id } => {
+                        state.selection(Selection::Clipboard, id.map(|o| o.id()))
+                    }
+                    $device::Event::PrimarySelection { id } => {
+                        state.selection(Selection::Primary, id.map(|o| o.id()))
+                    }
+                    $device::Event::Finished => state.finished = true,
+                    _ => {}
+                }
+            }
+
+            event_created_child!(State, $device_ty, [
+                $device::EVT_DATA_OFFER_OPCODE => ($offer_ty, ()),
+            ]);
+        }
+
+        impl Dispatch<$offer_ty, ()> for State {
+            fn event(
+                state: &mut Self,
+                offer: &$offer_ty,
+                event: $offer::Event,
+                _: &(),
+                _: &Connection,
+                _: &QueueHandle<Self>,
+            ) {
+                if let $offer::Event::Offer { mime_type } = event {
+                    state.offer_mime(&offer.id(), mime_type);
+                }
+            }
+        }
+
+        impl Dispatch<$source_ty, ()> for State {
+            fn event(
+                state: &mut Self,
+                _: &$source_ty,
+                event: $source::Event,
+                _: &(),
+                _: &Connection,
+                _: &QueueHandle<Self>,
+            ) {
+                match event {
+                    $source::Event::Send { mime_type, fd } => state.send(&mime_type, fd),
+                    $source::Event::Cancelled => state.cancelled = true,
+                    _ => {}
+                }
+            }
+        }
+    };
+}
+
+data_control_dispatch!(
+    Ext,
+    ext_manager::ExtDataControlManagerV1,
+    ext_device,
+    ext_device::ExtDataControlDeviceV1,
+    ext_offer,
+    ext_offer::ExtDataControlOfferV1,
+    ext_source,
+    ext_source::ExtDataControlSourceV1
+);
+data_control_dispatch!(
+    Wlr,
+    wlr_manager::ZwlrDataControlManagerV1,
+    wlr_device,
+    wlr_device::ZwlrDataControlDeviceV1,
+    wlr_offer,
+    wlr_offer::ZwlrDataControlOfferV1,
+    wlr_source,
+    wlr_source::ZwlrDataControlSourceV1
+);
+
+fn connect() -> Result<(Connection, GlobalList, EventQueue<State>, Manager, Device)> {
+    let conn = Connection::connect_to_env()?;
+    let (globals, queue) = registry_queue_init::<State>(&conn)?;
+    let qh = queue.handle();
+    let seat: WlSeat = globals.bind(&qh, 1..=1, ())?;
+    let manager = Manager::bind(&globals, &qh)?;
+    let device = manager.get_data_device(&seat, &qh);
+    Ok((conn, globals, queue, manager, device))
+}
+
+/// Follows the Wayland selection through a data-control device, which sees
+/// every change regardless of which window has focus.
+pub struct WaylandSource {
+    conn: Connection,
+    queue: EventQueue<State>,
+    state: State,
+    _device: Device,
+}
+
+impl WaylandSource {
+    pub fn connect() -> Result<Self> {
+        let (conn, globals, mut queue, _manager, device) = connect()?;
+        // Optional, only used to tell which app copied something
+        let _ = globals.bind::<toplevel_manager::ZwlrForeignToplevelManagerV1, _, _>(
+            &queue.handle(),
+            1..=3,
+            (),
+        );
+        let mut state = State::default();
+        // The compositor announces the current selections right away
+        queue.roundtrip(&mut state)?;
+        state.changed.clear();
+        Ok(Self {
+            conn,
+            queue,
+            state,
+            _device: device,
+        })
+    }
+
+    fn receive(&self, offer: &Offer, mime: &str) -> Result<Vec<u8>> {
+        let (mut reader, writer) = UnixStream::pair()?;
+        offer.receive(mime, writer.as_fd());
+        self.conn.flush()?;
+        // Only the source may hold the write end, or EOF never comes
+        drop(writer);
+
+        reader.set_read_timeout(Some(RECEIVE_TIMEOUT))?;
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        Ok(data)
+    }
+}
+
+impl ChangeSource for WaylandSource {
+    fn wait(&mut self) -> Result<Option<Selection>> {
+        loop {
+            if let Some(selection) = self.state.changed.pop_front() {
+                return Ok(Some(selection));
+            }
+            if self.state.finished {
+                bail!("Data-control device was destroyed by the compositor");
+            }
+            self.queue.blocking_dispatch(&mut self.state)?;
+        }
+    }
+
+    fn read(&mut self, selection: Selection, capture: &Capture) -> Result<Option<SelectionData>> {
+        // Picks up offers that arrived since the last wait, e.g. while PRIMARY settles
+        self.queue.roundtrip(&mut self.state)?;
+        let Some((offer, mimes)) = self.state.selections.get(&selection) else {
+            return Ok(None);
+        };
+        read_offered(mimes, capture, |mime| self.receive(offer, mime))
+    }
+
+    // Data-control doesn't say who set the selection, but that is almost
+    // always the focused window
+    fn owner(&mut self, _selection: Selection) -> Vec<String> {
+        self.state
+            .toplevels
+            .values()
+            .filter(|t| t.activated && !t.app_id.is_empty())
+            .map(|t| t.app_id.clone())
+            .collect()
+    }
+}
+
+/// Takes the Wayland `selection` and serves `targets` (MIME type and data)
+/// from a background thread until another client takes it over.
+pub fn set_selection(targets: Vec<(String, Vec<u8>)>, selection: Selection) -> Result<()> {
+    let (conn, _globals, mut queue, manager, device) = connect()?;
+    let qh = queue.handle();
+
+    let source = manager.create_data_source(&qh);
+    for (mime, _) in &targets {
+        source.offer(mime);
+    }
+    device.set_selection(selection, &source)?;
+
+    let mut state = State {
+        serving: targets.into_iter().collect(),
+        ..Default::default()
+    };
+    queue.roundtrip(&mut state)?;
+
+    thread::spawn(move || {
+        while !state.cancelled && !state.finished {
+            if let Err(e) = queue.blocking_dispatch(&mut state) {
+                eprintln!("Wayland clipboard connection lost: {e}");
+                break;
+            }
+        }
+        source.destroy();
+        device.destroy();
+        let _ = conn.flush();
+    });
+    Ok(())
+}
@@ -0,0 +1,598 @@
+use anyhow::{Result, anyhow, bail};
+use std::collections::{HashMap, VecDeque};
+use std::thread;
+use std::time::{Duration, Instant};
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::Event;
+use x11rb::protocol::xfixes::{ConnectionExt as _, SelectionEventMask};
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ChangeWindowAttributesAux, ClientMessageEvent, ConnectionExt as _,
+    CreateWindowAux, EventMask, KEY_PRESS_EVENT, KEY_RELEASE_EVENT, PropMode, Property,
+    PropertyNotifyEvent, SELECTION_NOTIFY_EVENT, SelectionNotifyEvent, SelectionRequestEvent,
+    Window, WindowClass,
+};
+use x11rb::protocol::xtest::ConnectionExt as _;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+
+use crate::clip::{ChangeSource, SelectionData, read_offered};
+use crate::prefs::Capture;
+use crate::types::Selection;
+
+// An owner that never answers would otherwise hang the watcher
+const TRANSFER_TIMEOUT: Duration = Duration::from_secs(2);
+// Largest chunk of an INCR transfer we hand out
+const INCR_CHUNK: usize = 256 * 1024;
+// How long the window manager gets to hand the focus back before pasting
+const PASTE_FOCUS_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Creates the invisible window selections are converted to and owned by.
+fn create_window(conn: &RustConnection, screen_num: usize) -> Result<Window> {
+    let root = conn.setup().roots[screen_num].root;
+    let window = conn.generate_id()?;
+    conn.create_window(
+        x11rb::COPY_DEPTH_FROM_PARENT,
+        window,
+        root,
+        0,
+        0,
+        1,
+        1,
+        0,
+        WindowClass::INPUT_ONLY,
+        x11rb::COPY_FROM_PARENT,
+        // Large transfers arrive in chunks announced through property changes
+        &CreateWindowAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+    )?;
+    Ok(window)
+}
+
+fn intern(conn: &RustConnection, name: &str) -> Result<Atom> {
+    Ok(conn.intern_atom(false, name.as_bytes())?.reply()?.atom)
+}
+
+/// The WM_CLASS instance and class of `window`.
+fn wm_class(conn: &RustConnection, window: Window) -> Vec<String> {
+    if let Ok(cookie) =
+        conn.get_property(false, window, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, 256)
+        && let Ok(reply) = cookie.reply()
+    {
+        return reply
+            .value
+            .split(|&b| b == 0)
+            .filter(|s| !s.is_empty())
+            .map(|s| String::from_utf8_lossy(s).into_owned())
+            .collect();
+    }
+    Vec::new()
+}
+
+fn selection_atom(conn: &RustConnection, selection: Selection) -> Result<Atom> {
+    match selection {
+        Selection::Clipboard => intern(conn, "CLIPBOARD"),
+        Selection::Primary => Ok(AtomEnum::PRIMARY.into()),
+    }
+}
+
+/// Blocks on XFixes selection-owner notifications for the CLIPBOARD and
+/// PRIMARY selections, so the watcher only wakes up when something was
+/// actually copied or selected. Also reads every target of a selection, which
+/// arboard can't.
+pub struct XFixesSource {
+    conn: RustConnection,
+    window: Window,
+    clipboard: Atom,
+    targets: Atom,
+    incr: Atom,
+    property: Atom,
+    client_leader: Atom,
+    net_wm_pid: Atom,
+    // Notifications that arrived while a selection was being read
+    pending: VecDeque<Selection>,
+    atom_names: HashMap<Atom, String>,
+}
+
+impl XFixesSource {
+    pub fn connect() -> Result<Self> {
+        let (conn, screen_num) = x11rb::connect(None)?;
+
+        conn.xfixes_query_version(5, 0)?.reply()?;
+
+        // XFixes needs a window to deliver the events to
+        let window = create_window(&conn, screen_num)?;
+
+        let clipboard = selection_atom(&conn, Selection::Clipboard)?;
+        for selection in [clipboard, AtomEnum::PRIMARY.into()] {
+            conn.xfixes_select_selection_input(
+                window,
+                selection,
+                SelectionEventMask::SET_SELECTION_OWNER
+                    | SelectionEventMask::SELECTION_WINDOW_DESTROY
+                    | SelectionEventMask::SELECTION_CLIENT_CLOSE,
+            )?;
+        }
+        conn.flush()?;
+
+        Ok(Self {
+            targets: intern(&conn, "TARGETS")?,
+            incr: intern(&conn, "INCR")?,
+            property: intern(&conn, "CLIPVAULT_SELECTION")?,
+            client_leader: intern(&conn, "WM_CLIENT_LEADER")?,
+            net_wm_pid: intern(&conn, "_NET_WM_PID")?,
+            conn,
+            window,
+            clipboard,
+            pending: VecDeque::new(),
+            atom_names: HashMap::new(),
+        })
+    }
+
+    fn selection_of(&self, atom: Atom) -> Selection {
+        if atom == self.clipboard {
+            Selection::Clipboard
+        } else {
+            Selection::Primary
+        }
+    }
+
+    fn atom_name(&mut self, atom: Atom) -> Result<String> {
+        if let Some(name) = self.atom_names.get(&atom) {
+            return Ok(name.clone());
+        }
+        let name =
+            String::from_utf8_lossy(&self.conn.get_atom_name(atom)?.reply()?.name).into_owned();
+        self.atom_names.insert(atom, name.clone());
+        Ok(name)
+    }
+
+    fn selection_owner(&self, selection: Selection) -> Result<Window> {
+        let selection = selection_atom(&self.conn, selection)?;
+        Ok(self.conn.get_selection_owner(selection)?.reply()?.owner)
+    }
+
+    fn property32(&self, window: Window, property: Atom, type_: AtomEnum) -> Option<u32> {
+        let reply = self
+            .conn
+            .get_property(false, window, property, type_, 0, 1)
+            .ok()?
+            .reply()
+            .ok()?;
+        reply.value32()?.next()
+    }
+
+    /// Names the client owning `window` goes by: its WM_CLASS instance and
+    /// class, and the name of its process.
+    fn client_names(&self, window: Window) -> Vec<String> {
+        let mut names = wm_class(&self.conn, window);
+        if let Some(pid) = self.property32(window, self.net_wm_pid, AtomEnum::CARDINAL)
+            && let Ok(comm) = std::fs::read_to_string(format!("/proc/{pid}/comm"))
+        {
+            names.push(comm.trim().to_string());
+        }
+        names
+    }
+
+    /// Next event other than a selection-owner notification, which is queued
+    /// for `wait` instead.
+    fn next_event(&mut self, deadline: Instant) -> Result<Event> {
+        loop {
+            match self.conn.poll_for_event()? {
+                Some(Event::XfixesSelectionNotify(ev)) => {
+                    let selection = self.selection_of(ev.selection);
+                    self.pending.push_back(selection);
+                }
+                Some(event) => return Ok(event),
+                None if Instant::now() >= deadline => bail!("Selection owner didn't answer"),
+                None => thread::sleep(Duration::from_millis(5)),
+            }
+        }
+    }
+
+    /// Transfers `target` of `selection`, `None` if the owner refused it.
+    fn convert(&mut self, selection: Atom, target: Atom) -> Result<Option<Vec<u8>>> {
+        self.conn.convert_selection(
+            self.window,
+            selection,
+            target,
+            self.property,
+            x11rb::CURRENT_TIME,
+        )?;
+        self.conn.flush()?;
+
+        let deadline = Instant::now() + TRANSFER_TIMEOUT;
+        loop {
+            if let Event::SelectionNotify(ev) = self.next_event(deadline)?
+                && ev.requestor == self.window
+                && ev.target == target
+            {
+                if ev.property == x11rb::NONE {
+                    return Ok(None);
+                }
+                break;
+            }
+        }
+
+        let reply = self
+            .conn
+            .get_property(true, self.window, self.property, AtomEnum::ANY, 0, u32::MAX)?
+            .reply()?;
+        if reply.type_ != self.incr {
+            return Ok(Some(reply.value));
+        }
+
+        // INCR: the owner writes one chunk at a time, each time we delete the
+        // property, and ends with an empty one
+        let mut data = Vec::new();
+        let mut deadline = Instant::now() + TRANSFER_TIMEOUT;
+        loop {
+            if let Event::PropertyNotify(ev) = self.next_event(deadline)?
+                && ev.window == self.window
+                && ev.atom == self.property
+                && ev.state == Property::NEW_VALUE
+            {
+                let chunk = self
+                    .conn
+                    .get_property(true, self.window, self.property, AtomEnum::ANY, 0, u32::MAX)?
+                    .reply()?;
+                if chunk.value.is_empty() {
+                    return Ok(Some(data));
+                }
+                data.extend_from_slice(&chunk.value);
+                deadline = Instant::now() + TRANSFER_TIMEOUT;
+            }
+        }
+    }
+}
+
+impl ChangeSource for XFixesSource {
+    fn wait(&mut self) -> Result<Option<Selection>> {
+        if let Some(selection) = self.pending.pop_front() {
+            return Ok(Some(selection));
+        }
+        loop {
+            if let Event::XfixesSelectionNotify(ev) = self.conn.wait_for_event()? {
+                return Ok(Some(self.selection_of(ev.selection)));
+            }
+        }
+    }
+
+    fn read(&mut self, selection: Selection, capture: &Capture) -> Result<Option<SelectionData>> {
+        let selection = selection_atom(&self.conn, selection)?;
+        // No owner, or one that can't even list its targets
+        let Some(list) = self.convert(selection, self.targets)? else {
+            return Ok(None);
+        };
+
+        let mut offered: Vec<(String, Atom)> = Vec::new();
+        for atom in list
+            .chunks_exact(4)
+            .map(|b| u32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+        {
+            // The owner may list atoms that don't exist, those are skipped
+            if let Ok(name) = self.atom_name(atom) {
+                offered.push((name, atom));
+            }
+        }
+        let mimes: Vec<String> = offered.iter().map(|(name, _)| name.clone()).collect();
+
+        read_offered(&mimes, capture, |mime| {
+            let &(_, atom) = offered
+                .iter()
+                .find(|(name, _)| name == mime)
+                .ok_or_else(|| anyhow!("Selection owner doesn't offer {mime}"))?;
+            self.convert(selection, atom)?
+                .ok_or_else(|| anyhow!("Selection owner refused {mime}"))
+        })
+    }
+
+    fn owner(&mut self, selection: Selection) -> Vec<String> {
+        let owner = match self.selection_owner(selection) {
+            Ok(owner) if owner != x11rb::NONE => owner,
+            _ => return Vec::new(),
+        };
+
+        let mut names = self.client_names(owner);
+        // Toolkits own selections from a hidden helper window, the client
+        // leader is the one with the class set
+        if let Some(leader) = self.property32(owner, self.client_leader, AtomEnum::WINDOW)
+            && leader != owner
+        {
+            names.extend(self.client_names(leader));
+        }
+        names
+    }
+}
+
+/// Takes `selection` and serves `targets` (name and data) from a background
+/// thread until another client takes it over.
+pub fn set_selection(targets: Vec<(String, Vec<u8>)>, selection: Selection) -> Result<()> {
+    let (conn, screen_num) = x11rb::connect(None)?;
+    let window = create_window(&conn, screen_num)?;
+    let selection = selection_atom(&conn, selection)?;
+    let targets_atom = intern(&conn, "TARGETS")?;
+    let incr = intern(&conn, "INCR")?;
+
+    let mut offered: Vec<(Atom, Vec<u8>)> = Vec::with_capacity(targets.len());
+    for (name, data) in targets {
+        offered.push((intern(&conn, &name)?, data));
+    }
+
+    conn.set_selection_owner(window, selection, x11rb::CURRENT_TIME)?;
+    if conn.get_selection_owner(selection)?.reply()?.owner != window {
+        bail!("Couldn't take the selection");
+    }
+
+    let mut server = Server {
+        conn,
+        targets_atom,
+        incr,
+        offered,
+        transfers: Vec::new(),
+    };
+    thread::spawn(move || {
+        let mut owner = true;
+        loop {
+            // A requestor that stopped deleting the property went away
+            server
+                .transfers
+                .retain(|t| t.last.elapsed() < TRANSFER_TIMEOUT);
+            // Transfers under way finish even once another client took over
+            if !owner && server.transfers.is_empty() {
+                break;
+            }
+            let event = if server.transfers.is_empty() {
+                server.conn.wait_for_event().map(Some)
+            } else {
+                server.conn.poll_for_event()
+            };
+            match event {
+                Ok(Some(Event::SelectionClear(_))) => owner = false,
+                Ok(Some(Event::SelectionRequest(req))) if owner => {
+                    if let Err(e) = server.answer(&req) {
+                        eprintln!("Failed to hand out the selection: {e}");
+                    }
+                }
+                Ok(Some(Event::PropertyNotify(ev))) if ev.state == Property::DELETE => {
+                    if let Err(e) = server.send_chunk(&ev) {
+                        eprintln!("Failed to hand out the selection: {e}");
+                    }
+                }
+                Ok(Some(_)) => {}
+                Ok(None) => thread::sleep(Duration::from_millis(5)),
+                Err(e) => {
+                    eprintln!("X11 selection connection lost: {e}");
+                    break;
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+/// A target too big for a single request, handed out in chunks each time
+/// the requestor deletes the property it read the last one from.
+struct Incr {
+    requestor: Window,
+    property: Atom,
+    target: Atom,
+    // Index into `Server::offered`
+    data: usize,
+    sent: usize,
+    last: Instant,
+}
+
+/// What `set_selection` serves, and the INCR transfers under way.
+struct Server {
+    conn: RustConnection,
+    targets_atom: Atom,
+    incr: Atom,
+    offered: Vec<(Atom, Vec<u8>)>,
+    transfers: Vec<Incr>,
+}
+
+impl Server {
+    fn chunk_len(&self) -> usize {
+        INCR_CHUNK.min(self.conn.maximum_request_bytes().saturating_sub(64))
+    }
+
+    fn answer(&mut self, req: &SelectionRequestEvent) -> Result<()> {
+        // Obsolete clients leave the property unset and expect the target's name
+        let property = if req.property == x11rb::NONE {
+            req.target
+        } else {
+            req.property
+        };
+
+        let served = if req.target == self.targets_atom {
+            let mut atoms: Vec<Atom> = self.offered.iter().map(|(atom, _)| *atom).collect();
+            atoms.push(self.targets_atom);
+            self.conn.change_property32(
+                PropMode::REPLACE,
+                req.requestor,
+                property,
+                AtomEnum::ATOM,
+                &atoms,
+            )?;
+            true
+        } else if let Some(i) = self
+            .offered
+            .iter()
+            .position(|(atom, _)| *atom == req.target)
+        {
+            let data = &self.offered[i].1;
+            if data.len() <= self.chunk_len() {
+                self.conn.change_property8(
+                    PropMode::REPLACE,
+                    req.requestor,
+                    property,
+                    req.target,
+                    data,
+                )?;
+            } else {
+                // INCR: the property announces a lower bound of the size, the
+                // requestor deleting it asks for the first chunk
+                self.conn.change_window_attributes(
+                    req.requestor,
+                    &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+                )?;
+                let size = u32::try_from(data.len()).unwrap_or(u32::MAX);
+                self.conn.change_property32(
+                    PropMode::REPLACE,
+                    req.requestor,
+                    property,
+                    self.incr,
+                    &[size],
+                )?;
+                self.transfers.push(Incr {
+                    requestor: req.requestor,
+                    property,
+                    target: req.target,
+                    data: i,
+                    sent: 0,
+                    last: Instant::now(),
+                });
+            }
+            true
+        } else {
+            false
+        };
+
+        let notify = SelectionNotifyEvent {
+            response_type: SELECTION_NOTIFY_EVENT,
+            sequence: 0,
+            time: req.time,
+            requestor: req.requestor,
+            selection: req.selection,
+            target: req.target,
+            property: if served { property } else { x11rb::NONE },
+        };
+        self.conn
+            .send_event(false, req.requestor, EventMask::NO_EVENT, notify)?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// Writes the next chunk of the INCR transfer whose property was just
+    /// deleted, or the empty one that ends it.
+    fn send_chunk(&mut self, ev: &PropertyNotifyEvent) -> Result<()> {
+        let Some(i) = self
+            .transfers
+            .iter()
+            .position(|t| t.requestor == ev.window && t.property == ev.atom)
+        else {
+            return Ok(());
+        };
+        let chunk_len = self.chunk_len();
+        let t = &mut self.transfers[i];
+        let data = &self.offered[t.data].1;
+        let end = (t.sent + chunk_len).min(data.len());
+        self.conn.change_property8(
+            PropMode::REPLACE,
+            t.requestor,
+            t.property,
+            t.target,
+            &data[t.sent..end],
+        )?;
+
+        if t.sent == data.len() {
+            let done = self.transfers.swap_remove(i);
+            if !self.transfers.iter().any(|t| t.requestor == done.requestor) {
+                self.conn.change_window_attributes(
+                    done.requestor,
+                    &ChangeWindowAttributesAux::new().event_mask(EventMask::NO_EVENT),
+                )?;
+            }
+        } else {
+            t.sent = end;
+            t.last = Instant::now();
+        }
+        self.conn.flush()?;
+        Ok(())
+    }
+}
+
+fn active_window_of(conn: &RustConnection, root: Window, net_active: Atom) -> Result<Window> {
+    let reply = conn
+        .get_property(false, root, net_active, AtomEnum::WINDOW, 0, 1)?
+        .reply()?;
+    Ok(reply
+        .value32()
+        .and_then(|mut v| v.next())
+        .unwrap_or(x11rb::NONE))
+}
+
+/// The window that has the focus, going by the window manager's
+/// `_NET_ACTIVE_WINDOW`.
+pub fn active_window() -> Result<Option<Window>> {
+    let (conn, screen_num) = x11rb::connect(None)?;
+    let root = conn.setup().roots[screen_num].root;
+    let net_active = intern(&conn, "_NET_ACTIVE_WINDOW")?;
+    let window = active_window_of(&conn, root, net_active)?;
+    Ok((window != x11rb::NONE).then_some(window))
+}
+
+/// First keycode producing `keysym`.
+fn keycode(conn: &RustConnection, keysym: u32) -> Result<u8> {
+    let setup = conn.setup();
+    let (min, max) = (setup.min_keycode, setup.max_keycode);
+    let mapping = conn.get_keyboard_mapping(min, max - min + 1)?.reply()?;
+    let per = mapping.keysyms_per_keycode.max(1) as usize;
+    mapping
+        .keysyms
+        .chunks(per)
+        .position(|syms| syms.contains(&keysym))
+        .map(|i| min + i as u8)
+        .ok_or_else(|| anyhow!("No key produces keysym {keysym:#x}"))
+}
+
+/// Activates `window` and types the paste shortcut into it through XTest:
+/// Ctrl+Shift+V if its WM_CLASS matches one of `terminals`, Ctrl+V otherwise.
+pub fn paste_into(window: Window, terminals: &[String]) -> Result<()> {
+    const XK_SHIFT_L: u32 = 0xffe1;
+    const XK_CONTROL_L: u32 = 0xffe3;
+    const XK_V: u32 = 0x0076;
+
+    let (conn, screen_num) = x11rb::connect(None)?;
+    conn.xtest_get_version(2, 2)?.reply()?;
+    let root = conn.setup().roots[screen_num].root;
+    let net_active = intern(&conn, "_NET_ACTIVE_WINDOW")?;
+
+    // Source indication 2: the request comes from a pager-like tool, which
+    // focus stealing prevention lets through
+    let activate =
+        ClientMessageEvent::new(32, window, net_active, [2, x11rb::CURRENT_TIME, 0, 0, 0]);
+    conn.send_event(
+        false,
+        root,
+        EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY,
+        activate,
+    )?;
+    conn.flush()?;
+
+    let deadline = Instant::now() + PASTE_FOCUS_TIMEOUT;
+    while active_window_of(&conn, root, net_active)? != window {
+        if Instant::now() >= deadline {
+            bail!("Window {window:#x} didn't get the focus");
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    let class = wm_class(&conn, window);
+    let terminal = class
+        .iter()
+        .any(|c| terminals.iter().any(|t| t.eq_ignore_ascii_case(c)));
+    let mut keys = vec![keycode(&conn, XK_CONTROL_L)?];
+    if terminal {
+        keys.push(keycode(&conn, XK_SHIFT_L)?);
+    }
+    keys.push(keycode(&conn, XK_V)?);
+
+    for &key in &keys {
+        conn.xtest_fake_input(KEY_PRESS_EVENT, key, x11rb::CURRENT_TIME, root, 0, 0, 0)?;
+    }
+    for &key in keys.iter().rev() {
+        conn.xtest_fake_input(KEY_RELEASE_EVENT, key, x11rb::CURRENT_TIME, root, 0, 0, 0)?;
+    }
+    conn.sync()?;
+    Ok(())
+}